@@ -1,55 +1,161 @@
-use std::io::Read;
+use alloc::{vec, vec::Vec};
+use core::mem;
+#[cfg(feature = "std")]
+use std::io::Seek;
 
-use crate::error::DecodingError;
-use crate::structs::DisposalMethod;
+#[cfg(feature = "std")]
+use crate::error::ErrorKind;
+use crate::error::{DecodingError, Warning};
+#[cfg(feature = "std")]
+use crate::index::FrameIndex;
+use crate::io::Read;
+use crate::options::{DecodeMode, Region};
+use crate::pixel::{self, BufferLayout, GifColor, Pixel};
+use crate::render;
+use crate::scale::{self, ScaleGrid};
+use crate::structs::{Comment, DisposalMethod, LoopCount};
 use crate::{decoder::Decoder};
 
-use crate::render::GifColor as Color;
-
-pub struct GifStream<R> {
+pub struct GifStream<R, P: Pixel = GifColor> {
     decoder: Decoder<R>,
 
-    canvas: Vec<Color>,
-    last_canvas: Vec<Color>,
+    // Part of the logical screen covered by the canvas
+    area: Region,
+    // Downscaling of the canvas, if enabled
+    grid: Option<ScaleGrid>,
+    canvas: Vec<P>,
+    last_canvas: Vec<P>,
 
     last_disposal: DisposalMethod,
     last_rect: (u16, u16, u16, u16),
 
-    bg_color: Color,
+    bg_color: P,
+
+    // Index buffer reused from frame to frame
+    indices: Vec<u8>,
 }
 
 impl<R: Read> GifStream<R> {
-    pub fn new(mut decoder: Decoder<R>) -> Result<Self, DecodingError> {
-        let width = decoder.screen_descriptor.width as usize;
-        let height = decoder.screen_descriptor.height as usize;
-        let pixel_count = width * height;
+    pub fn new(decoder: Decoder<R>) -> Result<Self, DecodingError> {
+        Self::with_format(decoder)
+    }
+}
 
-        let bg_color = Color::transparent();
+impl<R: Read, P: Pixel> GifStream<R, P> {
+    /// Creates a stream of canvases with pixels in the format `P`
+    pub fn with_format(
+        mut decoder: Decoder<R>,
+    ) -> Result<Self, DecodingError> {
+        let screen = Region::new(
+            0,
+            0,
+            decoder.screen_descriptor.width,
+            decoder.screen_descriptor.height,
+        );
+        let area = match decoder.region() {
+            Some(region) => screen.intersect(&region),
+            None => screen,
+        };
+        let grid = decoder.scale_grid();
+        let pixel_count = match &grid {
+            Some(grid) => grid.width() * grid.height(),
+            None => area.width as usize * area.height as usize,
+        };
+
+        // Current and previous canvas, and the copy returned by
+        // `next`
+        let pixel_size = mem::size_of::<P>() as u64;
+        decoder.allocate(3 * pixel_size * pixel_count as u64)?;
+
+        let bg_color = P::transparent();
 
         Ok(Self {
             decoder,
+            area,
+            grid,
             canvas: vec![bg_color; pixel_count],
             last_canvas: vec![bg_color; pixel_count],
             last_disposal: DisposalMethod::NoAction,
             last_rect: (0, 0, 0, 0),
             bg_color,
+            indices: Vec::new(),
         })
     }
 
-    fn dispose_previous(&mut self, screen_width: usize) {
+    /// Returns the part of the logical screen covered by the canvases,
+    /// the whole screen unless a region is set in `DecodeOptions`
+    pub fn canvas_region(&self) -> Region {
+        self.area
+    }
+
+    /// Returns the width and height of the canvases, in scaled pixels
+    /// if a scale is set in `DecodeOptions`
+    pub fn canvas_size(&self) -> (usize, usize) {
+        match &self.grid {
+            Some(grid) => (grid.width(), grid.height()),
+            None => (self.area.width as usize, self.area.height as usize),
+        }
+    }
+
+    /// See [`Decoder::loop_count`]
+    pub fn loop_count(&self) -> Option<LoopCount> {
+        self.decoder.loop_count()
+    }
+
+    /// See [`Decoder::set_render_plain_text`]
+    pub fn set_render_plain_text(&mut self, enabled: bool) {
+        self.decoder.set_render_plain_text(enabled);
+    }
+
+    /// See [`Decoder::set_apply_color_profile`]
+    #[cfg(feature = "std")]
+    pub fn set_apply_color_profile(&mut self, enabled: bool) {
+        self.decoder.set_apply_color_profile(enabled);
+    }
+
+    /// See [`Decoder::comments`]
+    pub fn comments(&self) -> &[Comment] {
+        self.decoder.comments()
+    }
+
+    /// See [`Decoder::xmp_packet`]
+    pub fn xmp_packet(&self) -> Option<&[u8]> {
+        self.decoder.xmp_packet()
+    }
+
+    /// See [`Decoder::warnings`]
+    pub fn warnings(&self) -> &[Warning] {
+        self.decoder.warnings()
+    }
+
+    /// See [`Decoder::take_warnings`]
+    pub fn take_warnings(&mut self) -> Vec<Warning> {
+        self.decoder.take_warnings()
+    }
+
+    fn dispose_previous(&mut self) {
         let (x, y, w, h) = self.last_rect;
 
         match self.last_disposal {
             DisposalMethod::NoAction | DisposalMethod::DoNotDispose => {},
 
+            DisposalMethod::RestoreBackground if self.grid.is_some() => {
+                self.restore_background_scaled(Region::new(x, y, w, h));
+            },
+
             DisposalMethod::RestoreBackground => {
-                for row in 0..h {
-                    for col in 0..w {
-                        let idx = (y + row) as usize * screen_width + (x + col) as usize;
-                        if idx < self.canvas.len() {
-                            self.canvas[idx] = self.bg_color;
-                        }
-                    }
+                let visible = Region::new(x, y, w, h).intersect(&self.area);
+                if visible.is_empty() {
+                    return;
+                }
+                let canvas_width = self.area.width as usize;
+
+                for row in 0..visible.height as usize {
+                    let start = (visible.top - self.area.top) as usize + row;
+                    let start = start * canvas_width
+                        + (visible.left - self.area.left) as usize;
+                    self.canvas[start..start + visible.width as usize]
+                        .fill(self.bg_color);
                 }
             },
 
@@ -62,44 +168,488 @@ impl<R: Read> GifStream<R> {
     }
 }
 
-impl<R: Read> Iterator for GifStream<R> {
-    type Item = Result<Vec<Color>, DecodingError>;
-
-    fn next(&mut self) -> Option<Self::Item> {
-        let raw_frame = match self.decoder.next_frame() {
-            Ok(Some(f)) => f,
-            Ok(None) => return None,
-            Err(e) => return Some(Err(e)),
+impl<R, P: Pixel> GifStream<R, P> {
+    /// Clears the part of the scaled canvas covered by `rect`, cells
+    /// partially covered become partially transparent
+    fn restore_background_scaled(&mut self, rect: Region) {
+        let Some(grid) = &self.grid else {
+            return;
         };
 
-        let screen_width = self.decoder.screen_descriptor.width as usize;
+        let canvas_width = grid.width();
+        let (x0, y0, x1, y1) = grid.cover(rect);
+
+        for cy in y0..y1 {
+            for cx in x0..x1 {
+                let (area, inside) = grid.coverage(cx, cy, rect);
+                let pixel = &mut self.canvas[cy * canvas_width + cx];
+
+                if inside == area {
+                    *pixel = self.bg_color;
+                } else if inside > 0 {
+                    let mut color = pixel.to_rgba();
+                    color.a = (color.a as usize * (area - inside) / area) as u8;
+                    *pixel = P::from_rgba(color);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl<R: Read + Seek, P: Pixel> GifStream<R, P> {
+    /// Moves the stream so that the next call to `next` returns the
+    /// canvas after frame `n`
+    ///
+    /// Decoding restarts from the nearest key frame of `index`, the
+    /// frames in between are composited without being returned.
+    /// When plain text is rendered, `n` counts images only: the text
+    /// frames between image `n - 1` and image `n` come first.
+    pub fn seek_to_frame(
+        &mut self,
+        index: &FrameIndex,
+        n: usize,
+    ) -> Result<(), DecodingError> {
+        if n >= index.len() {
+            return Err(ErrorKind::FrameOutOfRange {
+                frame: n,
+                len: index.len(),
+            }
+            .into());
+        }
+
+        let key_frame = index.key_frame_for(n);
+        self.decoder.seek_to_frame(index, key_frame)?;
+
+        self.canvas.fill(self.bg_color);
+        self.last_disposal = DisposalMethod::NoAction;
+        self.last_rect = (0, 0, 0, 0);
+
+        while self.decoder.images_returned() < n {
+            if !self.advance()? {
+                break;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl<R: Read, P: Pixel> GifStream<R, P> {
+    /// Decodes the next frame and composites it on the canvas
+    /// Returns false at the end of the stream
+    fn advance(&mut self) -> Result<bool, DecodingError> {
+        if let Some(grid) = self.grid {
+            return self.advance_scaled(grid);
+        }
+
+        let indices = mem::take(&mut self.indices);
+        let (mut raw_frame, progress) =
+            match self.decoder.next_frame_indices(indices)? {
+                Some(f) => f,
+                None => return Ok(false),
+            };
 
-        self.dispose_previous(screen_width);
+        let table = pixel::lookup_table::<P>(
+            &raw_frame.palette,
+            raw_frame.transparent_index,
+        );
+
+        // Pixels left undecoded by an incomplete frame are not drawn,
+        // unless they are filled with the last row
+        let mut drawn = Vec::new();
+        if !raw_frame.complete
+            && self.decoder.mode() == DecodeMode::Lenient
+        {
+            let fill = self.decoder.incomplete_fill();
+
+            drawn = vec![true; raw_frame.indices.len()];
+            render::fill_incomplete(&mut drawn, &progress, fill, false);
+            render::fill_incomplete(
+                &mut raw_frame.indices,
+                &progress,
+                fill,
+                0,
+            );
+        }
+
+        self.dispose_previous();
 
         if raw_frame.disposal == DisposalMethod::RestorePrevious {
             self.last_canvas.copy_from_slice(&self.canvas);
         }
 
-        for (i, &pixel_color) in raw_frame.pixels.iter().enumerate() {
-            if pixel_color.a == 0 {
-                continue;
-            }
+        let frame_rect = Region::new(
+            raw_frame.left,
+            raw_frame.top,
+            raw_frame.width,
+            raw_frame.height,
+        );
+        let visible = frame_rect.intersect(&self.area);
+        let frame_width = raw_frame.width as usize;
+        let canvas_width = self.area.width as usize;
 
-            let local_x = i % raw_frame.width as usize;
-            let local_y = i / raw_frame.width as usize;
+        for y in visible.top..visible.top + visible.height {
+            let frame_row = (y - raw_frame.top) as usize * frame_width;
+            let canvas_row = (y - self.area.top) as usize * canvas_width;
 
-            let global_x = raw_frame.left as usize + local_x;
-            let global_y = raw_frame.top as usize + local_y;
+            for x in visible.left..visible.left + visible.width {
+                let i = frame_row + (x - raw_frame.left) as usize;
+                let index = raw_frame.indices[i];
+                if Some(index) == raw_frame.transparent_index
+                    || drawn.get(i) == Some(&false)
+                {
+                    continue;
+                }
 
-            if global_x < screen_width && global_y < self.decoder.screen_descriptor.height as usize {
-                let canvas_idx = global_y * screen_width + global_x;
-                self.canvas[canvas_idx] = pixel_color;
+                let canvas_idx = canvas_row + (x - self.area.left) as usize;
+                self.canvas[canvas_idx] = table[index as usize];
             }
         }
 
         self.last_disposal = raw_frame.disposal;
         self.last_rect = (raw_frame.left, raw_frame.top, raw_frame.width, raw_frame.height);
+        self.indices = raw_frame.indices;
+
+        Ok(true)
+    }
+}
+
+impl<R: Read, P: Pixel> GifStream<R, P> {
+    /// Same as `advance`, on the scaled canvas
+    fn advance_scaled(
+        &mut self,
+        grid: ScaleGrid,
+    ) -> Result<bool, DecodingError> {
+        let (info, scaled) = match self.decoder.next_scaled_frame(&grid)? {
+            Some(f) => f,
+            None => return Ok(false),
+        };
+
+        self.dispose_previous();
+
+        if info.disposal == DisposalMethod::RestorePrevious {
+            self.last_canvas.copy_from_slice(&self.canvas);
+        }
+
+        let rect = Region::new(info.left, info.top, info.width, info.height);
+        let canvas_width = grid.width();
+
+        for i in 0..scaled.width * scaled.height {
+            let color = scaled.pixel(&grid, rect, i);
+            if color.a == 0 {
+                continue;
+            }
+
+            let x = scaled.left + i % scaled.width;
+            let y = scaled.top + i / scaled.width;
+            let pixel = &mut self.canvas[y * canvas_width + x];
+            *pixel = match color.a {
+                255 => P::from_rgba(color),
+                _ => P::from_rgba(scale::over(color, pixel.to_rgba())),
+            };
+        }
+
+        self.last_disposal = info.disposal;
+        self.last_rect = (info.left, info.top, info.width, info.height);
+
+        Ok(true)
+    }
+
+    /// Composites the next frame and copies the canvas to `buffer`,
+    /// placed according to `layout`
+    /// Returns false at the end of the stream, the buffer is then left
+    /// untouched.
+    pub fn next_into(
+        &mut self,
+        buffer: &mut [u8],
+        layout: BufferLayout,
+    ) -> Result<bool, DecodingError> {
+        let (width, height) = self.canvas_size();
+        layout.check::<P>(width, height, buffer.len())?;
+
+        if !self.advance()? {
+            return Ok(false);
+        }
+
+        if width > 0 {
+            for (y, row) in self.canvas.chunks_exact(width).enumerate() {
+                pixel::write_row(row, layout.row::<P>(buffer, y, width));
+            }
+        }
+
+        Ok(true)
+    }
+}
+
+impl<R: Read, P: Pixel> Iterator for GifStream<R, P> {
+    type Item = Result<Vec<P>, DecodingError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.advance() {
+            Ok(true) => Some(Ok(self.canvas.clone())),
+            Ok(false) => None,
+            Err(e) => Some(Err(e)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[cfg(feature = "std")]
+    use std::io::Cursor;
+
+    use super::*;
+    use crate::options::{DecodeOptions, Scale, ScaleFilter};
+    use crate::pixel::Bgra8;
+    use crate::test_gif::{self, TestFrame};
+
+    fn palette() -> Vec<u8> {
+        (0..8u8).flat_map(|i| [i * 30, 100, 255 - i * 30]).collect()
+    }
+
+    /// A 10x10 animation restoring the background after each frame,
+    /// with a frame outside the logical screen and one partly outside
+    fn outside_screen() -> Vec<u8> {
+        let mut frames = [
+            TestFrame::new(0, 0, 6, 6),
+            TestFrame::new(20, 8, 5, 5),
+            TestFrame::new(7, 7, 5, 5),
+            TestFrame::new(1, 1, 4, 4),
+        ];
+        for frame in &mut frames {
+            frame.disposal = 2;
+        }
+        test_gif::gif(10, 10, &palette(), &frames)
+    }
+
+    fn canvases(decoder: Decoder<&[u8]>) -> Vec<Vec<GifColor>> {
+        let stream = decoder.into_stream().unwrap();
+        stream.map(Result::unwrap).collect()
+    }
+
+    #[test]
+    fn frames_outside_the_screen() {
+        let data = outside_screen();
+        let expected = canvases(Decoder::new(&data[..]).unwrap());
+        assert_eq!(expected.len(), 4);
+
+        // Nothing of the frame outside is drawn, the first one is
+        // disposed of
+        let drawn = |canvas: &[GifColor]| {
+            canvas.iter().filter(|c| c.a == 255).count()
+        };
+        assert_eq!(drawn(&expected[1]), 0);
+        assert_eq!(drawn(&expected[2]), 9);
+
+        let decoder = Decoder::new(&data[..]).unwrap();
+        let mut stream = decoder.into_stream_as::<Bgra8>().unwrap();
+        let mut buffer = [0u8; 400];
+        let layout = BufferLayout::packed::<Bgra8>(10);
+        for canvas in &expected {
+            assert!(stream.next_into(&mut buffer, layout).unwrap());
+            for (c, bytes) in canvas.iter().zip(buffer.chunks(4)) {
+                assert_eq!(bytes, [c.b, c.g, c.r, c.a]);
+            }
+        }
+        assert!(!stream.next_into(&mut buffer, layout).unwrap());
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn frames_outside_the_screen_on_threads_and_seeking() {
+        let data = outside_screen();
+        let expected = canvases(Decoder::new(&data[..]).unwrap());
+
+        let options = DecodeOptions::new().threads(3);
+        let decoder =
+            Decoder::with_options(&data[..], options).unwrap();
+        assert_eq!(canvases(decoder), expected);
+
+        let mut decoder = Decoder::new(Cursor::new(&data)).unwrap();
+        let index = decoder.build_frame_index().unwrap();
+        let mut stream = decoder.into_stream().unwrap();
+        for n in (0..expected.len()).rev() {
+            stream.seek_to_frame(&index, n).unwrap();
+            assert_eq!(stream.next().unwrap().unwrap(), expected[n]);
+        }
+    }
+
+    /// Cuts `region` out of canvases of the given width
+    fn window(
+        canvases: &[Vec<GifColor>],
+        width: usize,
+        region: Region,
+    ) -> Vec<Vec<GifColor>> {
+        let (left, top) = (region.left as usize, region.top as usize);
+        let len = region.width as usize;
+        canvases
+            .iter()
+            .map(|canvas| {
+                canvas
+                    .chunks(width)
+                    .skip(top)
+                    .take(region.height as usize)
+                    .flat_map(|row| &row[left..left + len])
+                    .copied()
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn region_of_the_canvas() {
+        let data = outside_screen();
+        let full = canvases(Decoder::new(&data[..]).unwrap());
+
+        // The second frame is outside of the screen, the third one is
+        // disjoint from the first region
+        let regions = [
+            Region::new(0, 0, 5, 10),
+            Region::new(2, 3, 7, 4),
+            Region::new(6, 6, 4, 4),
+        ];
+        for region in regions {
+            let options = DecodeOptions::new().region(region);
+            let decoder =
+                Decoder::with_options(&data[..], options).unwrap();
+            assert_eq!(canvases(decoder), window(&full, 10, region));
+        }
+    }
+
+    /// Scaled canvases cover the screen rounded up, each pixel is
+    /// either the top left pixel of its cell or the average of the
+    /// cell, with the transparent pixels lowering the alpha
+    #[test]
+    fn scaled_canvases() {
+        let mut frame = TestFrame::new(0, 0, 10, 7);
+        frame.transparent = Some(0);
+        let data = test_gif::gif(10, 7, &palette(), &[frame]);
+        let full = &canvases(Decoder::new(&data[..]).unwrap())[0];
+
+        let scaled = |scale, filter| {
+            let options = DecodeOptions::new()
+                .scale(scale)
+                .scale_filter(filter);
+            let decoder =
+                Decoder::with_options(&data[..], options).unwrap();
+            let stream = GifStream::new(decoder).unwrap();
+            let size = stream.canvas_size();
+            (size, stream.map(Result::unwrap).next().unwrap())
+        };
+
+        let (size, _) = scaled(Scale::Quarter, ScaleFilter::Box);
+        assert_eq!(size, (3, 2));
+
+        let (size, point) = scaled(Scale::Half, ScaleFilter::Point);
+        assert_eq!(size, (5, 4));
+        for (i, &pixel) in point.iter().enumerate() {
+            let (x, y) = (i % 5 * 2, i / 5 * 2);
+            assert_eq!(pixel, full[y * 10 + x]);
+        }
+
+        let (_, boxed) = scaled(Scale::Half, ScaleFilter::Box);
+        for (i, &pixel) in boxed.iter().enumerate() {
+            let (x, y) = (i % 5 * 2, i / 5 * 2);
+            let cell: Vec<GifColor> = (y..(y + 2).min(7))
+                .flat_map(|y| &full[y * 10 + x..y * 10 + x + 2])
+                .copied()
+                .collect();
+            let opaque: Vec<_> =
+                cell.iter().filter(|c| c.a == 255).collect();
+
+            let count = opaque.len() as u32;
+            let average = |channel: fn(&GifColor) -> u8| {
+                let sum: u32 =
+                    opaque.iter().map(|&c| channel(c) as u32).sum();
+                ((sum + count / 2) / count.max(1)) as u8
+            };
+            let area = cell.len() as u32;
+            let expected = match count {
+                0 => GifColor::transparent(),
+                _ => GifColor::new(
+                    average(|c| c.r),
+                    average(|c| c.g),
+                    average(|c| c.b),
+                    ((count * 255 + area / 2) / area) as u8,
+                ),
+            };
+            assert_eq!(pixel, expected);
+        }
+    }
+
+    /// The canvases returned by `next` count once towards the limit
+    #[test]
+    fn canvas_copies_are_charged_once() {
+        let frames: Vec<_> =
+            (0..20).map(|x| TestFrame::new(x, 0, 1, 1)).collect();
+        let data = test_gif::gif(40, 30, &palette(), &frames);
+
+        let canvas_bytes =
+            40 * 30 * mem::size_of::<GifColor>() as u64;
+        let options =
+            DecodeOptions::new().max_total_bytes(4 * canvas_bytes);
+        let decoder =
+            Decoder::with_options(&data[..], options).unwrap();
+        let stream = GifStream::new(decoder).unwrap();
+        assert_eq!(stream.map(Result::unwrap).count(), 20);
+    }
+
+    /// With plain text rendered, seeking counts the images, also
+    /// when frames are decoded ahead on threads
+    #[cfg(feature = "std")]
+    #[test]
+    fn seek_with_plain_text() {
+        let data = test_gif::text_and_comments();
+        let mut decoder = Decoder::new(&data[..]).unwrap();
+        decoder.set_render_plain_text(true);
+        let expected = canvases(decoder);
+        assert_eq!(expected.len(), 4);
+
+        for threads in [1, 2] {
+            let options = DecodeOptions::new().threads(threads);
+            let mut decoder =
+                Decoder::with_options(Cursor::new(&data), options)
+                    .unwrap();
+            decoder.set_render_plain_text(true);
+            let index = decoder.build_frame_index().unwrap();
+            let mut stream = decoder.into_stream().unwrap();
+
+            // The text drawn after image 0 comes before image 1
+            for (n, canvas) in [(2, 3), (1, 1), (0, 0)] {
+                stream.seek_to_frame(&index, n).unwrap();
+                assert_eq!(
+                    stream.next().unwrap().unwrap(),
+                    expected[canvas]
+                );
+            }
+        }
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn seek_past_the_last_frame() {
+        let data = test_gif::animation();
+        let mut decoder = Decoder::new(Cursor::new(&data)).unwrap();
+        let index = decoder.build_frame_index().unwrap();
+        let len = index.len();
+        assert!(!index.is_key_frame(len));
+
+        let mut stream = GifStream::new(decoder).unwrap();
+        for n in [len, len + 1, usize::MAX] {
+            let error = stream.seek_to_frame(&index, n).unwrap_err();
+            assert_eq!(
+                error.kind(),
+                ErrorKind::FrameOutOfRange { frame: n, len }
+            );
+        }
 
-        Some(Ok(self.canvas.clone()))
+        let expected = GifStream::new(Decoder::new(&data[..]).unwrap())
+            .unwrap()
+            .last()
+            .unwrap()
+            .unwrap();
+        stream.seek_to_frame(&index, len - 1).unwrap();
+        assert_eq!(stream.next().unwrap().unwrap(), expected);
     }
 }
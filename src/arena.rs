@@ -0,0 +1,657 @@
+//! Decoding in a fixed amount of memory supplied by the caller
+//!
+//! `ArenaDecoder` allocates nothing: the canvas, the palettes and the
+//! row of indices live in a byte arena, the LZW dictionary in a
+//! `LzwTables`. The memory needed only depends on the size of the
+//! logical screen, see `arena_size`, so that it can be reserved up
+//! front on targets without a heap for the pixels.
+
+use core::marker::PhantomData;
+
+use crate::{
+    decoder::{self, RowTarget},
+    error::{DecodingError, ErrorKind},
+    frame::FrameInfo,
+    io::Read,
+    lzw::LzwDecoder,
+    options::DecodeMode,
+    pixel::{GifColor, Pixel},
+    reader::{PositionReader, SubBlockBufReader},
+    structs::{
+        Color, DisposalMethod, GraphicControl, ImageDescriptor,
+        LogicalScreenDescriptor, LoopCount, Version,
+    },
+};
+
+pub use crate::lzw::LzwTables;
+
+/// Bytes of arena needed by an `ArenaDecoder` with pixels in the
+/// format `P`, for a GIF with the logical screen `screen`
+///
+/// This is the whole memory used for decoding besides the `LzwTables`
+/// and the decoder itself, both of fixed size: the current and the
+/// previous canvas, a conversion table, the global palette and a row
+/// of indices.
+pub fn arena_size<P: Pixel>(
+    screen: &LogicalScreenDescriptor,
+) -> usize {
+    let width = screen.width as usize;
+    let canvas = width
+        .saturating_mul(screen.height as usize)
+        .saturating_mul(P::SIZE);
+
+    canvas
+        .saturating_mul(2)
+        .saturating_add(256 * P::SIZE + 3 * 256 + width)
+}
+
+/// Decoder compositing the frames on a canvas held in a caller supplied
+/// arena, the changed rows are passed to a display callback after each
+/// frame
+///
+/// Frames wider than the logical screen are not supported, plain text
+/// is not rendered and the other extensions are skipped except for the
+/// loop count.
+pub struct ArenaDecoder<'a, R, P: Pixel = GifColor> {
+    reader: PositionReader<R>,
+    pub version: Version,
+    pub screen_descriptor: LogicalScreenDescriptor,
+    mode: DecodeMode,
+
+    tables: &'a mut LzwTables,
+    // Pixels of `P` as written by `Pixel::write_bytes`
+    canvas: &'a mut [u8],
+    last_canvas: &'a mut [u8],
+    // Palette of the current frame converted to `P`
+    table: &'a mut [u8],
+    // RGB triplets, empty without a Global Color Table
+    global_palette: &'a [u8],
+    row: &'a mut [u8],
+
+    control: Option<GraphicControl>,
+    loop_count: Option<LoopCount>,
+    last_disposal: DisposalMethod,
+    last_rect: (u16, u16, u16, u16),
+    // The canvas has been passed to the display
+    shown: bool,
+    finished: bool,
+    // Start of the block being read and number of frames, to locate
+    // errors
+    block_offset: u64,
+    frame_count: usize,
+
+    _pixel: PhantomData<P>,
+}
+
+impl<'a, R: Read> ArenaDecoder<'a, R> {
+    pub fn new(
+        reader: R,
+        tables: &'a mut LzwTables,
+        arena: &'a mut [u8],
+    ) -> Result<Self, DecodingError> {
+        Self::with_format(
+            reader,
+            tables,
+            arena,
+            DecodeMode::default(),
+        )
+    }
+}
+
+impl<'a, R: Read, P: Pixel> ArenaDecoder<'a, R, P> {
+    /// Creates a decoder with pixels in the format `P`, reading the
+    /// header and the Global Color Table
+    /// `arena` must hold at least `arena_size::<P>` bytes for the
+    /// logical screen.
+    pub fn with_format(
+        reader: R,
+        tables: &'a mut LzwTables,
+        arena: &'a mut [u8],
+        mode: DecodeMode,
+    ) -> Result<Self, DecodingError> {
+        let mut reader = PositionReader::new(reader);
+        let (version, screen_descriptor) =
+            decoder::read_header(&mut reader)
+                .map_err(|e| e.at(0, None))?;
+
+        let required = arena_size::<P>(&screen_descriptor);
+        if arena.len() < required {
+            return Err(ErrorKind::InvalidBuffer {
+                required,
+                len: arena.len(),
+            }
+            .into());
+        }
+
+        let width = screen_descriptor.width as usize;
+        let canvas_bytes =
+            width * screen_descriptor.height as usize * P::SIZE;
+
+        let (canvas, rest) = arena.split_at_mut(canvas_bytes);
+        let (last_canvas, rest) = rest.split_at_mut(canvas_bytes);
+        let (table, rest) = rest.split_at_mut(256 * P::SIZE);
+        let (global_palette, rest) = rest.split_at_mut(3 * 256);
+        let row = &mut rest[..width];
+
+        let global_len = if screen_descriptor.has_global_color_table()
+        {
+            screen_descriptor.global_color_table_size()
+        } else {
+            0
+        };
+        let global_palette = &mut global_palette[..3 * global_len];
+        reader
+            .read_exact(global_palette)
+            .map_err(|e| DecodingError::from(e).at(13, None))?;
+
+        for pixel in canvas.chunks_exact_mut(P::SIZE) {
+            P::transparent().write_bytes(pixel);
+        }
+
+        Ok(Self {
+            reader,
+            version,
+            screen_descriptor,
+            mode,
+            tables,
+            canvas,
+            last_canvas,
+            table,
+            global_palette,
+            row,
+            control: None,
+            loop_count: None,
+            last_disposal: DisposalMethod::NoAction,
+            last_rect: (0, 0, 0, 0),
+            shown: false,
+            finished: false,
+            block_offset: 0,
+            frame_count: 0,
+            _pixel: PhantomData,
+        })
+    }
+
+    /// See [`Decoder::loop_count`](crate::decoder::Decoder::loop_count)
+    pub fn loop_count(&self) -> Option<LoopCount> {
+        self.loop_count
+    }
+
+    /// Decodes the next frame, composites it on the canvas and passes
+    /// the rows of the canvas that changed to `display`, with their
+    /// index and their pixels as written by `Pixel::write_bytes`
+    /// Every row is passed after the first frame. Returns `None` at the
+    /// end of the stream.
+    ///
+    /// Pixels missing from a truncated frame are not drawn.
+    pub fn next_frame(
+        &mut self,
+        display: impl FnMut(usize, &[u8]),
+    ) -> Result<Option<FrameInfo>, DecodingError> {
+        self.read_frame(display).map_err(|e| {
+            e.at(self.block_offset, self.frame_count.checked_sub(1))
+        })
+    }
+
+    fn read_frame(
+        &mut self,
+        mut display: impl FnMut(usize, &[u8]),
+    ) -> Result<Option<FrameInfo>, DecodingError> {
+        let Some(descriptor) = self.next_descriptor()? else {
+            return Ok(None);
+        };
+
+        if descriptor.width > self.screen_descriptor.width {
+            return Err(ErrorKind::Unsupported(
+                "Frame wider than the logical screen in an arena",
+            )
+            .into());
+        }
+
+        let control = self.control.take().unwrap_or_default();
+        let transparent_index = control.transparent_color_index;
+        let palette_len = self.read_table(&descriptor)?;
+
+        let dirty = self.dirty_rows(&descriptor);
+        self.dispose_previous();
+        if control.disposal_method == DisposalMethod::RestorePrevious
+        {
+            self.last_canvas.copy_from_slice(self.canvas);
+        }
+
+        self.block_offset = self.reader.position();
+        let mut min_code_size = [0u8; 1];
+        let decoded = match self.reader.read_exact(&mut min_code_size)
+        {
+            Ok(()) => {
+                decoder::check_min_code_size(
+                    min_code_size[0],
+                    self.mode,
+                )?;
+                self.draw(
+                    &descriptor,
+                    min_code_size[0],
+                    transparent_index,
+                    palette_len,
+                )?
+            }
+            Err(_) if self.mode == DecodeMode::Lenient => 0,
+            Err(e) => return Err(e.into()),
+        };
+
+        let width = self.screen_descriptor.width as usize * P::SIZE;
+        if width > 0 {
+            for y in dirty {
+                display(y, &self.canvas[y * width..(y + 1) * width]);
+            }
+        }
+        self.shown = true;
+
+        self.last_disposal = control.disposal_method;
+        self.last_rect = (
+            descriptor.left,
+            descriptor.top,
+            descriptor.width,
+            descriptor.height,
+        );
+
+        Ok(Some(FrameInfo {
+            delay_cs: control.delay_time_cs,
+            disposal: control.disposal_method,
+            left: descriptor.left,
+            top: descriptor.top,
+            width: descriptor.width,
+            height: descriptor.height,
+            transparent_index,
+            complete: decoded
+                == descriptor.width as usize
+                    * descriptor.height as usize,
+        }))
+    }
+
+    /// Reads the blocks up to the next Image Descriptor
+    fn next_descriptor(
+        &mut self,
+    ) -> Result<Option<ImageDescriptor>, DecodingError> {
+        if self.finished {
+            return Ok(None);
+        }
+
+        loop {
+            self.block_offset = self.reader.position();
+
+            let mut introducer = [0u8; 1];
+            if self.reader.read(&mut introducer)? == 0 {
+                // As `Decoder::next_record`
+                self.finished = true;
+                return match self.mode {
+                    DecodeMode::Lenient => Ok(None),
+                    DecodeMode::Strict => {
+                        Err(ErrorKind::MissingTrailer.into())
+                    }
+                    DecodeMode::Standard => {
+                        Err(ErrorKind::UnexpectedEof.into())
+                    }
+                };
+            }
+
+            match introducer[0] {
+                0x2C => {
+                    self.frame_count += 1;
+                    let descriptor = decoder::read_image_descriptor(
+                        &mut self.reader,
+                    )?;
+                    if self.mode == DecodeMode::Strict {
+                        decoder::check_image_descriptor(
+                            &descriptor,
+                            &self.screen_descriptor,
+                        )?;
+                    }
+                    return Ok(Some(descriptor));
+                }
+                0x21 => self.read_extension()?,
+                0x3B => {
+                    self.finished = true;
+                    return Ok(None);
+                }
+                0x00 => continue,
+                introducer => {
+                    return Err(
+                        ErrorKind::UnknownBlock(introducer).into()
+                    );
+                }
+            }
+        }
+    }
+
+    fn read_extension(&mut self) -> Result<(), DecodingError> {
+        if self.mode == DecodeMode::Strict
+            && self.version == Version::Gif87a
+        {
+            return Err(ErrorKind::ExtensionInGif87a.into());
+        }
+
+        let mut label = [0u8; 1];
+        self.reader.read_exact(&mut label)?;
+
+        let mut netscape = false;
+        match label[0] {
+            0xF9 => {
+                self.control =
+                    Some(decoder::read_graphic_control_ext(
+                        &mut self.reader,
+                        self.mode,
+                    )?);
+                return Ok(());
+            }
+            0xFF => {
+                let mut header = [0u8; 12];
+                self.reader.read_exact(&mut header)?;
+                if header[0] != 11 {
+                    return Err(
+                        ErrorKind::InvalidExtensionSize.into()
+                    );
+                }
+                netscape = matches!(
+                    &header[1..],
+                    b"NETSCAPE2.0" | b"ANIMEXTS1.0"
+                );
+            }
+            // Plain text is not rendered but uses up the Graphic
+            // Control Extension
+            0x01 => self.control = None,
+            _ => {}
+        }
+
+        let mut block = [0u8; 255];
+        loop {
+            let mut len = [0u8; 1];
+            self.reader.read_exact(&mut len)?;
+            let data = &mut block[..len[0] as usize];
+            if data.is_empty() {
+                return Ok(());
+            }
+            self.reader.read_exact(data)?;
+
+            if let [1, lo, hi, ..] = *data
+                && netscape
+            {
+                self.loop_count =
+                    Some(LoopCount::from(u16::from_le_bytes([
+                        lo, hi,
+                    ])));
+            }
+        }
+    }
+
+    /// Converts the palette of the frame to `P` and returns its length,
+    /// indices beyond it are black
+    fn read_table(
+        &mut self,
+        descriptor: &ImageDescriptor,
+    ) -> Result<usize, DecodingError> {
+        let black = P::from_color(Color::default());
+        for pixel in self.table.chunks_exact_mut(P::SIZE) {
+            black.write_bytes(pixel);
+        }
+
+        let to_pixel = |rgb: &[u8]| {
+            P::from_color(Color {
+                r: rgb[0],
+                g: rgb[1],
+                b: rgb[2],
+            })
+        };
+
+        if descriptor.has_local_palette() {
+            let len = descriptor.local_palette_size();
+            let mut rgb = [0u8; 3];
+            for pixel in
+                self.table.chunks_exact_mut(P::SIZE).take(len)
+            {
+                self.reader.read_exact(&mut rgb)?;
+                to_pixel(&rgb).write_bytes(pixel);
+            }
+            return Ok(len);
+        }
+
+        if self.global_palette.is_empty() {
+            return Err(ErrorKind::MissingPalette.into());
+        }
+
+        for (pixel, rgb) in self
+            .table
+            .chunks_exact_mut(P::SIZE)
+            .zip(self.global_palette.chunks_exact(3))
+        {
+            to_pixel(rgb).write_bytes(pixel);
+        }
+        Ok(self.global_palette.len() / 3)
+    }
+
+    /// Decodes the image data on the canvas, returns how many pixels
+    /// have been decoded
+    fn draw(
+        &mut self,
+        descriptor: &ImageDescriptor,
+        min_code_size: u8,
+        transparent_index: Option<u8>,
+        palette_len: usize,
+    ) -> Result<usize, DecodingError> {
+        let lenient = self.mode == DecodeMode::Lenient;
+        let mut target = CanvasRows {
+            row: &mut self.row[..descriptor.width as usize],
+            canvas: &mut *self.canvas,
+            table: &*self.table,
+            size: P::SIZE,
+            canvas_width: self.screen_descriptor.width as usize,
+            canvas_height: self.screen_descriptor.height as usize,
+            left: descriptor.left as usize,
+            top: descriptor.top as usize,
+            transparent_index,
+            palette_len,
+            check_indices: self.mode == DecodeMode::Strict,
+            invalid_index: None,
+        };
+
+        let mut blocks = SubBlockBufReader::new(&mut self.reader);
+        let (decoded, _) = decoder::decode_image_data(
+            LzwDecoder::with_tables(
+                &mut blocks,
+                min_code_size,
+                &mut *self.tables,
+            ),
+            descriptor,
+            self.mode,
+            &mut target,
+        )?;
+
+        if let Some(index) = target.invalid_index {
+            return Err(decoder::invalid_index_error(
+                index,
+                palette_len,
+            ));
+        }
+
+        match blocks.consume_to_end() {
+            Err(e) if !lenient => Err(DecodingError::image_data(e)),
+            _ => Ok(decoded),
+        }
+    }
+
+    /// Rows changed by the disposal of the previous frame and by the
+    /// frame `descriptor`
+    fn dirty_rows(
+        &self,
+        descriptor: &ImageDescriptor,
+    ) -> core::ops::Range<usize> {
+        let height = self.screen_descriptor.height as usize;
+        if !self.shown {
+            return 0..height;
+        }
+
+        let mut top = descriptor.top as usize;
+        let mut bottom = top + descriptor.height as usize;
+
+        let (_, y, _, h) = self.last_rect;
+        if matches!(
+            self.last_disposal,
+            DisposalMethod::RestoreBackground
+                | DisposalMethod::RestorePrevious
+        ) && h > 0
+        {
+            top = top.min(y as usize);
+            bottom = bottom.max(y as usize + h as usize);
+        }
+
+        top.min(height)..bottom.min(height)
+    }
+
+    fn dispose_previous(&mut self) {
+        let (x, y, w, h) = self.last_rect;
+        let canvas_width = self.screen_descriptor.width as usize;
+        let canvas_height = self.screen_descriptor.height as usize;
+
+        match self.last_disposal {
+            DisposalMethod::RestoreBackground => {
+                let left = (x as usize).min(canvas_width);
+                let right =
+                    (x as usize + w as usize).min(canvas_width);
+                let bottom =
+                    (y as usize + h as usize).min(canvas_height);
+
+                for row in y as usize..bottom {
+                    let start = (row * canvas_width + left) * P::SIZE;
+                    let end = (row * canvas_width + right) * P::SIZE;
+                    for pixel in self.canvas[start..end]
+                        .chunks_exact_mut(P::SIZE)
+                    {
+                        P::transparent().write_bytes(pixel);
+                    }
+                }
+            }
+            DisposalMethod::RestorePrevious => {
+                self.canvas.copy_from_slice(self.last_canvas);
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Rows decoded in a single row of indices and drawn on the canvas of
+/// an `ArenaDecoder`
+struct CanvasRows<'a> {
+    row: &'a mut [u8],
+    canvas: &'a mut [u8],
+    table: &'a [u8],
+    // Bytes per pixel
+    size: usize,
+    canvas_width: usize,
+    canvas_height: usize,
+    left: usize,
+    top: usize,
+    transparent_index: Option<u8>,
+    palette_len: usize,
+    check_indices: bool,
+    // First index beyond the palette, if `check_indices`
+    invalid_index: Option<u8>,
+}
+
+impl RowTarget for CanvasRows<'_> {
+    fn row_buffer(&mut self, _y: usize) -> &mut [u8] {
+        self.row
+    }
+
+    fn row_decoded(&mut self, y: usize, len: usize) {
+        let indices = &self.row[..len];
+
+        if self.check_indices && self.invalid_index.is_none() {
+            self.invalid_index = indices.iter().copied().find(|&i| {
+                i as usize >= self.palette_len
+                    && Some(i) != self.transparent_index
+            });
+        }
+
+        let y = self.top + y;
+        if y >= self.canvas_height {
+            return;
+        }
+
+        let visible = self.canvas_width.saturating_sub(self.left);
+        let size = self.size;
+        let start = (y * self.canvas_width + self.left) * size;
+
+        for (x, &index) in indices.iter().take(visible).enumerate() {
+            if Some(index) == self.transparent_index {
+                continue;
+            }
+
+            let at = start + x * size;
+            let color = index as usize * size;
+            self.canvas[at..at + size]
+                .copy_from_slice(&self.table[color..color + size]);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::{boxed::Box, vec, vec::Vec};
+
+    use super::*;
+    use crate::{decoder::Decoder, test_gif};
+
+    /// The displayed rows rebuild the canvases of `GifStream`, in an
+    /// arena of exactly `arena_size` bytes
+    #[test]
+    fn same_canvases_as_the_stream() {
+        let data = test_gif::animation();
+        let decoder = Decoder::new(&data[..]).unwrap();
+        let screen = decoder.screen_descriptor;
+        let expected: Vec<Vec<u8>> = decoder
+            .into_stream()
+            .unwrap()
+            .map(|canvas| {
+                let canvas = canvas.unwrap();
+                let bytes =
+                    canvas.iter().flat_map(|c| [c.r, c.g, c.b, c.a]);
+                bytes.collect()
+            })
+            .collect();
+
+        let size = arena_size::<GifColor>(&screen);
+        assert_eq!(size, 2 * 40 * 30 * 4 + 256 * 4 + 3 * 256 + 40);
+
+        let mut tables = Box::new(LzwTables::new());
+        let mut arena = vec![0; size];
+        let mut decoder =
+            ArenaDecoder::new(&data[..], &mut tables, &mut arena)
+                .unwrap();
+
+        let row_len = 40 * 4;
+        let mut shown = vec![0; 30 * row_len];
+        for canvas in &expected {
+            let display = |y: usize, row: &[u8]| {
+                shown[y * row_len..][..row_len].copy_from_slice(row)
+            };
+            assert!(decoder.next_frame(display).unwrap().is_some());
+            assert_eq!(&shown, canvas);
+        }
+        assert!(decoder.next_frame(|_, _| {}).unwrap().is_none());
+    }
+
+    #[test]
+    fn arena_too_small() {
+        let data = test_gif::animation();
+        let mut tables = Box::new(LzwTables::new());
+        let mut arena = vec![0; 11431];
+        let result =
+            ArenaDecoder::new(&data[..], &mut tables, &mut arena);
+        assert_eq!(
+            result.err().map(|e| e.kind()),
+            Some(ErrorKind::InvalidBuffer {
+                required: 11432,
+                len: 11431
+            })
+        );
+    }
+}
@@ -1,12 +1,14 @@
-use std::io::{self, Read};
+use crate::io::{self, BufRead};
 
+/// Reads the LZW codes from the bytes buffered by the input, without
+/// copying them
 pub struct BitReader<R> {
     input: R,
     bit_buffer: u64,
     bits_in_buffer: u8,
 }
 
-impl<R: Read> BitReader<R> {
+impl<R: BufRead> BitReader<R> {
     pub fn new(input: R) -> Self {
         Self {
             input,
@@ -15,32 +17,72 @@ impl<R: Read> BitReader<R> {
         }
     }
 
+    /// Gives access to the input, the bytes already in the bit buffer
+    /// have been consumed from it
+    pub fn get_mut(&mut self) -> &mut R {
+        &mut self.input
+    }
+
+    /// Returns true if whole bytes are left, in the bit buffer or in
+    /// the input
+    pub fn has_bytes_left(&mut self) -> bool {
+        self.bits_in_buffer >= 8
+            || self.input.fill_buf().is_ok_and(|b| !b.is_empty())
+    }
+
     pub fn read_bits(&mut self, n: u8) -> io::Result<u16> {
         if n > 16 {
             panic!("Cannot read more than 16 bits at time");
         }
 
-        while self.bits_in_buffer < n {
-            let mut byte = [0u8; 1];
-            let bytes_read = self.input.read(&mut byte)?;
+        if self.bits_in_buffer < n {
+            self.refill(n)?;
+        }
 
-            if bytes_read == 0 {
+        let result = (self.bit_buffer & ((1 << n) - 1)) as u16;
+
+        self.bit_buffer >>= n;
+        self.bits_in_buffer -= n;
+
+        Ok(result)
+    }
+
+    /// Fills the bit buffer from the input until `n` bits are available
+    fn refill(&mut self, n: u8) -> io::Result<()> {
+        loop {
+            let available = self.input.fill_buf()?;
+            if available.is_empty() {
                 return Err(io::Error::new(
                     io::ErrorKind::UnexpectedEof,
                     "End of stream while reading LZW bits",
                 ));
             }
 
-            self.bit_buffer |=
-                (byte[0] as u64) << self.bits_in_buffer;
-            self.bits_in_buffer += 8;
-        }
-
-        let result = (self.bit_buffer & ((1 << n) - 1)) as u16;
+            // Whole bytes that fit in the bit buffer
+            let room = ((64 - self.bits_in_buffer) / 8) as usize;
 
-        self.bit_buffer >>= n;
-        self.bits_in_buffer -= n;
+            let taken =
+                if let Some(word) = available.first_chunk::<8>() {
+                    // The bits of the bytes beyond `room` are set again,
+                    // to the same value, by the next refill
+                    self.bit_buffer |= u64::from_le_bytes(*word)
+                        << self.bits_in_buffer;
+                    self.bits_in_buffer += 8 * room as u8;
+                    room
+                } else {
+                    let take = room.min(available.len());
+                    for &byte in &available[..take] {
+                        self.bit_buffer |=
+                            (byte as u64) << self.bits_in_buffer;
+                        self.bits_in_buffer += 8;
+                    }
+                    take
+                };
+            self.input.consume(taken);
 
-        Ok(result)
+            if self.bits_in_buffer >= n {
+                return Ok(());
+            }
+        }
     }
 }
@@ -0,0 +1,176 @@
+//! Minimal replacements of the `std::io` items, see `crate::io`
+
+use alloc::collections::VecDeque;
+use core::{fmt, result};
+
+pub type Result<T> = result::Result<T, Error>;
+
+/// Category of an `Error`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ErrorKind {
+    /// The source ended before the expected data
+    UnexpectedEof,
+    InvalidData,
+    Other,
+}
+
+impl ErrorKind {
+    fn description(&self) -> &'static str {
+        match self {
+            ErrorKind::UnexpectedEof => "unexpected end of file",
+            ErrorKind::InvalidData => "invalid data",
+            ErrorKind::Other => "other error",
+        }
+    }
+}
+
+/// Error of a byte source, or of the data read from it
+#[derive(Debug, Clone)]
+pub struct Error {
+    kind: ErrorKind,
+    message: &'static str,
+}
+
+impl Error {
+    pub fn new(kind: ErrorKind, message: &'static str) -> Self {
+        Self { kind, message }
+    }
+
+    pub fn kind(&self) -> ErrorKind {
+        self.kind
+    }
+}
+
+impl From<ErrorKind> for Error {
+    fn from(kind: ErrorKind) -> Self {
+        Self::new(kind, kind.description())
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.message)
+    }
+}
+
+impl core::error::Error for Error {}
+
+/// A source of bytes, the subset of `std::io::Read` used by the
+/// decoder
+pub trait Read {
+    /// Reads some bytes into `buf` and returns how many, 0 at the end
+    /// of the source
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize>;
+
+    /// Fills `buf`, the end of the source is an `UnexpectedEof` error
+    fn read_exact(&mut self, mut buf: &mut [u8]) -> Result<()> {
+        while !buf.is_empty() {
+            match self.read(buf)? {
+                0 => {
+                    return Err(Error::new(
+                        ErrorKind::UnexpectedEof,
+                        "failed to fill whole buffer",
+                    ));
+                }
+                n => buf = &mut buf[n..],
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A source of bytes with an internal buffer, the subset of
+/// `std::io::BufRead` used by the decoder
+pub trait BufRead: Read {
+    /// Returns the buffered bytes, reading the source if there are
+    /// none. Empty at the end of the source.
+    fn fill_buf(&mut self) -> Result<&[u8]>;
+
+    /// Marks the first `amount` buffered bytes as read
+    fn consume(&mut self, amount: usize);
+}
+
+impl<R: Read + ?Sized> Read for &mut R {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        (**self).read(buf)
+    }
+}
+
+impl<B: BufRead + ?Sized> BufRead for &mut B {
+    fn fill_buf(&mut self) -> Result<&[u8]> {
+        (**self).fill_buf()
+    }
+
+    fn consume(&mut self, amount: usize) {
+        (**self).consume(amount)
+    }
+}
+
+impl Read for &[u8] {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        let n = self.len().min(buf.len());
+        buf[..n].copy_from_slice(&self[..n]);
+        *self = &self[n..];
+        Ok(n)
+    }
+}
+
+impl BufRead for &[u8] {
+    fn fill_buf(&mut self) -> Result<&[u8]> {
+        Ok(self)
+    }
+
+    fn consume(&mut self, amount: usize) {
+        *self = &self[amount..];
+    }
+}
+
+impl Read for VecDeque<u8> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        let n = self.len().min(buf.len());
+        for (dst, src) in buf.iter_mut().zip(self.drain(..n)) {
+            *dst = src;
+        }
+        Ok(n)
+    }
+}
+
+impl BufRead for VecDeque<u8> {
+    fn fill_buf(&mut self) -> Result<&[u8]> {
+        Ok(self.as_slices().0)
+    }
+
+    fn consume(&mut self, amount: usize) {
+        self.drain(..amount);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec::Vec;
+
+    use super::*;
+    use crate::{decoder::Decoder, pixel::GifColor, test_gif};
+
+    fn canvases(decoder: Decoder<impl Read>) -> Vec<Vec<GifColor>> {
+        let stream = decoder.into_stream().unwrap();
+        stream.map(|canvas| canvas.unwrap()).collect()
+    }
+
+    /// Without std, GIFs are read from slices and queues of bytes
+    #[test]
+    fn decode_without_std() {
+        let data = test_gif::animation();
+        let from_slice = canvases(Decoder::new(&data[..]).unwrap());
+        assert_eq!(from_slice.len(), 4);
+
+        let queue: VecDeque<u8> = data.iter().copied().collect();
+        let from_queue = canvases(Decoder::new(queue).unwrap());
+        assert_eq!(from_queue, from_slice);
+
+        let mut short = &data[..2];
+        let error = short.read_exact(&mut [0; 3]).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::UnexpectedEof);
+    }
+}
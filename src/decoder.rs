@@ -1,118 +1,502 @@
-use std::{io::Read, vec};
+use alloc::{
+    boxed::Box, collections::VecDeque, sync::Arc, vec, vec::Vec,
+};
+use core::{borrow::BorrowMut, mem};
+#[cfg(feature = "std")]
+use std::io::Seek;
 
 use crate::{
-    animator::GifStream, error::DecodingError, frame::Frame, lzw::LzwDecoder, reader::SubBlockReader, render::GifColor, structs::{
-        Color, DisposalMethod, GraphicControl, ImageDescriptor,
-        LogicalScreenDescriptor, Palette,
+    animator::GifStream, error::{DecodingError, ErrorKind, Warning, WarningKind}, frame::{Frame, FrameInfo, IndexedFrame}, interlace::RowOrder, io::{self, BufRead, Read}, lzw::{LzwDecoder, LzwTables}, options::{DecodeMode, DecodeOptions, IncompleteFill, Limit, Region, Scale}, parallel::{ImageJob, Workers}, reader::{PositionReader, SubBlockBufReader, SubBlockReader}, pixel::{self, BufferLayout, Pixel}, render::{self, FrameProgress}, scale::{self, ScaleGrid, ScaleRows, ScaledFrame}, structs::{
+        Color, Comment, DisposalMethod, GraphicControl, ImageDescriptor,
+        LogicalScreenDescriptor, LoopCount, Palette, PlainText, Version,
     }
 };
+#[cfg(feature = "std")]
+use crate::{
+    icc::IccProfile,
+    index::{FrameIndex, FrameIndexEntry, PaletteSource},
+};
+
+pub use crate::reader::SubBlocks;
 
 pub struct Decoder<R> {
-    reader: R,
+    reader: PositionReader<R>,
+    pub version: Version,
     pub screen_descriptor: LogicalScreenDescriptor,
-    pub global_palette: Option<Palette>,
+    pub global_palette: Option<Arc<Palette>>,
+
+    state: State,
+    options: DecodeOptions,
+    allocated_bytes: u64,
+    loop_count: Option<LoopCount>,
+    buffer_size: Option<u32>,
+    comments: Vec<Comment>,
+    // Offset of each comment, so that a comment read again after a
+    // seek is kept once
+    comment_offsets: Vec<u64>,
+    icc_profile: Option<Vec<u8>>,
+    #[cfg(feature = "std")]
+    color_profile: Option<IccProfile>,
+    // Global palette converted with the color profile, shared by the
+    // frames
+    #[cfg(feature = "std")]
+    converted_palette: Option<Arc<Palette>>,
+    #[cfg(feature = "std")]
+    apply_color_profile: bool,
+    xmp_packet: Option<Vec<u8>>,
+
+    // Number of image descriptors read so far
+    image_count: usize,
+    // Start of the block being read, see `DecodingError::offset`
+    block_offset: u64,
+    // Where the frame last taken from `pending` was read, errors
+    // found while finishing it are reported there
+    pending_location: Option<Location>,
+    // Defects of the GIF found so far, see `warnings`
+    warnings: Vec<Warning>,
+    render_plain_text: bool,
+    // Row of indices reused by `next_frame_into` and
+    // `decode_frame_rows`
+    row_buffer: Vec<u8>,
+    // Local Palette of the last image, refilled in place once the
+    // frames drawn with it are dropped
+    local_palette: Arc<Palette>,
+    // Dictionary of the LZW decoder, allocated with the first image
+    // and reused by the next ones
+    lzw_tables: Option<Box<LzwTables>>,
+    // Threads of `decode_ahead`
+    workers: Workers,
+    // Frames decoded ahead on the worker threads, in file order
+    // An error or the end of the frames ends its batch.
+    pending: VecDeque<PendingFrame>,
+    // Set by `from_slice`, the image data is then read in place
+    slice_input: Option<SliceInput<R>>,
+}
+
+/// Direct access to an input held in memory, see `Decoder::from_slice`
+struct SliceInput<R> {
+    /// Returns the bytes left in the input
+    remaining: fn(&R) -> &[u8],
+    /// Consumes the first bytes of the input
+    advance: fn(&mut R, usize),
 }
 
+impl<R> Clone for SliceInput<R> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<R> Copy for SliceInput<R> {}
+
+/// A single block of the GIF stream, in file order
+///
+/// `Header`, `LogicalScreen` and `GlobalPalette` are read by
+/// `Decoder::new` and replayed by the first calls to `next_record`.
+#[derive(Debug, Clone)]
 pub enum Block {
-    Image(ImageDescriptor, Option<GraphicControl>),
+    Header(Version),
+    LogicalScreen(LogicalScreenDescriptor),
+    GlobalPalette(Palette),
+    GraphicControl(GraphicControl),
+    Application {
+        identifier: [u8; 8],
+        auth_code: [u8; 3],
+        data: Vec<u8>,
+    },
+    Comment(Vec<u8>),
+    PlainText(PlainText),
+    Unknown {
+        label: u8,
+        data: Vec<u8>,
+    },
+    /// The image descriptor with its Local Palette, if any
+    ///
+    /// The compressed image data follows: it can be read with
+    /// `decode_frame_into`, `lzw_reader` or `image_data`, otherwise it
+    /// is skipped by the next call to `next_record`.
+    ImageDescriptor(ImageDescriptor, Option<Palette>),
     Trailer,
-    Extension,
 }
 
-impl<R: Read> Decoder<R> {
-    pub fn new(mut reader: R) -> Result<Self, DecodingError> {
-        let mut signature = [0u8; 6];
-        reader.read_exact(&mut signature)?;
+/// The compressed data of an image, borrowed from the input, see
+/// `Decoder::image_data`
+#[derive(Debug, Clone)]
+pub struct ImageData<'a> {
+    pub min_code_size: u8,
+    pub blocks: SubBlocks<'a>,
+}
 
-        if &signature != b"GIF89a" && &signature != b"GIF87a" {
-            return Err(DecodingError::InvalidSignature);
-        }
+/// The graphic rendering block starting a frame, with the Graphic
+/// Control Extension that applies to it
+enum FrameStart {
+    Image(
+        ImageDescriptor,
+        Option<Arc<Palette>>,
+        Option<GraphicControl>,
+    ),
+    PlainText(PlainText, Option<GraphicControl>),
+}
+
+/// A frame read ahead of its decompression
+enum AheadFrame {
+    /// A Plain Text frame, nothing left to decompress
+    Ready(IndexedFrame, FrameProgress),
+    /// An image with its data in the next `ImageJob` of the batch
+    Image(Option<Arc<Palette>>, Option<GraphicControl>),
+}
 
-        let mut lsd_buf = [0u8; 7];
-        reader.read_exact(&mut lsd_buf)?;
+/// Offset of a block and index of the frame, see
+/// `DecodingError::offset` and `DecodingError::frame`
+type Location = (u64, Option<usize>);
 
-        let screen_descriptor = LogicalScreenDescriptor {
-            width: u16::from_le_bytes([lsd_buf[0], lsd_buf[1]]),
-            height: u16::from_le_bytes([lsd_buf[2], lsd_buf[3]]),
-            packed_fields: lsd_buf[4],
-            bg_color_index: lsd_buf[5],
-            pixel_aspect_ration: lsd_buf[6],
-        };
+/// A frame decoded ahead, see `Decoder::decode_ahead`
+struct PendingFrame {
+    /// `None` past the last frame
+    result:
+        Result<Option<(IndexedFrame, FrameProgress)>, DecodingError>,
+    /// Where the frame was read
+    location: Location,
+    /// An image rather than a Plain Text frame
+    #[cfg(feature = "std")]
+    image: bool,
+    /// Found while reading and decoding the frame, reported once it
+    /// is returned
+    warnings: Vec<Warning>,
+}
+
+/// Frames read ahead for each decoding thread
+const FRAMES_PER_THREAD: usize = 4;
+
+/// Application identifier and authentication code of an embedded ICC
+/// profile
+const ICC_IDENTIFIER: [u8; 8] = *b"ICCRGBG1";
+const ICC_AUTH_CODE: [u8; 3] = *b"012";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    Header,
+    LogicalScreen,
+    GlobalPalette,
+    Blocks,
+    ImageData,
+    Done,
+}
+
+impl<R: Read> Decoder<R> {
+    pub fn new(reader: R) -> Result<Self, DecodingError> {
+        Self::with_options(reader, DecodeOptions::default())
+    }
+
+    pub fn with_options(
+        reader: R,
+        options: DecodeOptions,
+    ) -> Result<Self, DecodingError> {
+        let mut reader = PositionReader::new(reader);
+        let (version, screen_descriptor) =
+            read_header(&mut reader).map_err(|e| e.at(0, None))?;
+
+        let canvas_pixels = screen_descriptor.width as u64
+            * screen_descriptor.height as u64;
+        if options
+            .limits
+            .max_canvas_pixels
+            .is_some_and(|max| canvas_pixels > max)
+        {
+            return Err(DecodingError::from(ErrorKind::LimitExceeded(
+                Limit::CanvasPixels,
+            ))
+            .at(6, None));
+        }
 
         let global_palette = if screen_descriptor
             .has_global_color_table()
         {
             let size = screen_descriptor.global_color_table_size();
-            Some(Self::read_palette(&mut reader, size)?)
+            let mut palette = Palette::with_capacity(size);
+            Self::read_palette(&mut reader, size, &mut palette)
+                .map_err(|e| e.at(13, None))?;
+            Some(Arc::new(palette))
         } else {
             None
         };
 
-        Ok(Self {
+        let mut decoder = Self {
             reader,
+            version,
             screen_descriptor,
             global_palette,
-        })
+            state: State::Header,
+            workers: Workers::new(options.threads),
+            options,
+            allocated_bytes: 0,
+            loop_count: None,
+            buffer_size: None,
+            comments: Vec::new(),
+            comment_offsets: Vec::new(),
+            icc_profile: None,
+            #[cfg(feature = "std")]
+            color_profile: None,
+            #[cfg(feature = "std")]
+            converted_palette: None,
+            #[cfg(feature = "std")]
+            apply_color_profile: false,
+            xmp_packet: None,
+            image_count: 0,
+            block_offset: 0,
+            pending_location: None,
+            warnings: Vec::new(),
+            render_plain_text: false,
+            row_buffer: Vec::new(),
+            local_palette: Arc::default(),
+            lzw_tables: None,
+            pending: VecDeque::new(),
+            slice_input: None,
+        };
+
+        let palette_len = decoder.global_palette.as_ref().map_or(0, |p| p.len());
+        decoder.allocate(3 * palette_len as u64)?;
+
+        Ok(decoder)
+    }
+
+    /// Returns how many times the animation should be repeated
+    ///
+    /// `None` means that no looping extension has been found (yet):
+    /// the animation should be played once.
+    /// The extension usually precedes the first frame, so the value is
+    /// reliable after the first call to `next_frame`.
+    pub fn loop_count(&self) -> Option<LoopCount> {
+        self.loop_count
+    }
+
+    /// Returns the buffer size, in bytes, suggested by the
+    /// NETSCAPE2.0 buffering sub-block, if any
+    pub fn buffer_size(&self) -> Option<u32> {
+        self.buffer_size
+    }
+
+    /// When enabled, `next_frame` also returns a frame for every Plain
+    /// Text Extension, drawn with a built-in monospace font.
+    /// Disabled by default, as most viewers ignore them.
+    pub fn set_render_plain_text(&mut self, enabled: bool) {
+        self.render_plain_text = enabled;
+    }
+
+    /// Returns the Comment Extensions found so far, in file order
+    pub fn comments(&self) -> &[Comment] {
+        &self.comments
+    }
+
+    /// Returns the raw ICC profile embedded by the ICCRGBG1
+    /// application extension, if found so far
+    pub fn icc_profile(&self) -> Option<&[u8]> {
+        self.icc_profile.as_deref()
+    }
+
+    /// Returns the embedded ICC profile, if it is a supported
+    /// matrix/TRC RGB profile
+    #[cfg(feature = "std")]
+    pub fn color_profile(&self) -> Option<&IccProfile> {
+        self.color_profile.as_ref()
+    }
+
+    /// When enabled, `next_frame` converts the palette colors from the
+    /// embedded ICC profile to sRGB before mapping the indices.
+    /// Without a supported profile the colors are left untouched.
+    #[cfg(feature = "std")]
+    pub fn set_apply_color_profile(&mut self, enabled: bool) {
+        self.apply_color_profile = enabled;
+    }
+
+    /// Returns the XMP packet embedded by the "XMP Data" application
+    /// extension, if found so far, without the magic trailer
+    pub fn xmp_packet(&self) -> Option<&[u8]> {
+        self.xmp_packet.as_deref()
+    }
+
+    /// Returns the defects of the GIF found so far, that the decoder
+    /// worked around instead of failing
+    ///
+    /// Frames read again after a seek report their warnings again.
+    pub fn warnings(&self) -> &[Warning] {
+        &self.warnings
     }
 
+    /// Returns the warnings found so far and clears them
+    pub fn take_warnings(&mut self) -> Vec<Warning> {
+        mem::take(&mut self.warnings)
+    }
+
+    /// Returns the next block of the stream
+    ///
+    /// Once the trailer has been read, every call returns
+    /// `Block::Trailer`.
     pub fn next_record(&mut self) -> Result<Block, DecodingError> {
-        let mut current_graphic_control = None;
+        let mut block = self.next_block()?;
+        match &mut block {
+            Block::ImageDescriptor(descriptor, palette)
+                if descriptor.has_local_palette() =>
+            {
+                *palette = Some(self.local_palette.to_vec());
+            }
+            Block::Comment(data) => {
+                let i = self
+                    .comment_offsets
+                    .binary_search(&self.block_offset)
+                    .expect("comments are kept");
+                data.clone_from(&self.comments[i].data);
+            }
+            Block::Application {
+                identifier: ICC_IDENTIFIER,
+                auth_code: ICC_AUTH_CODE,
+                data,
+            } => {
+                let profile = self
+                    .icc_profile
+                    .as_ref()
+                    .expect("profiles are kept");
+                data.clone_from(profile);
+            }
+            _ => {}
+        }
+        Ok(block)
+    }
+
+    /// Same as `next_record`, but the data kept by the decoder is
+    /// left out of the block: the Local Palette of an image, the
+    /// content of a comment or of an ICC profile
+    fn next_block(&mut self) -> Result<Block, DecodingError> {
+        self.pending_location = None;
+        self.read_record().map_err(|e| self.locate(e))
+    }
+
+    fn read_record(&mut self) -> Result<Block, DecodingError> {
+        match self.state {
+            State::Header => {
+                self.state = State::LogicalScreen;
+                return Ok(Block::Header(self.version));
+            }
+            State::LogicalScreen => {
+                self.state = State::GlobalPalette;
+                return Ok(Block::LogicalScreen(self.screen_descriptor));
+            }
+            State::GlobalPalette => {
+                self.state = State::Blocks;
+                if let Some(palette) = &self.global_palette {
+                    return Ok(Block::GlobalPalette(palette.to_vec()));
+                }
+            }
+            State::ImageData => {
+                self.skip_image_data()?;
+            }
+            State::Blocks => {}
+            State::Done => return Ok(Block::Trailer),
+        }
 
         loop {
+            self.block_offset = self.reader.position();
+
             let mut introducer = [0u8; 1];
             if self.reader.read(&mut introducer)? == 0 {
-                return Err(DecodingError::Io(
-                    std::io::ErrorKind::UnexpectedEof.into(),
-                ));
+                // A truncated file ends where the data stops,
+                // as if the trailer was there
+                match self.options.mode {
+                    DecodeMode::Lenient => {
+                        self.state = State::Done;
+                        self.warn(WarningKind::MissingTrailer);
+                        return Ok(Block::Trailer);
+                    }
+                    DecodeMode::Strict => {
+                        return Err(ErrorKind::MissingTrailer.into());
+                    }
+                    DecodeMode::Standard => {}
+                }
+                return Err(ErrorKind::UnexpectedEof.into());
             }
 
             match introducer[0] {
                 // --- Image Separator (0x2C) ---
                 0x2C => {
                     let descriptor = self.read_image_descriptor()?;
-                    // TODO: 1. read LocalPalette
-                    // TODO: 2. read LZW data
-                    return Ok(Block::Image(
-                        descriptor,
-                        current_graphic_control,
+                    self.image_count += 1;
+
+                    if self.is_strict() {
+                        self.check_image_descriptor(&descriptor)?;
+                    } else if !self.screen_contains(&descriptor) {
+                        self.warn(WarningKind::FrameOutsideScreen);
+                    }
+
+                    if self
+                        .options
+                        .limits
+                        .max_frames
+                        .is_some_and(|max| self.image_count > max)
+                    {
+                        return Err(ErrorKind::LimitExceeded(
+                            Limit::Frames,
+                        )
+                        .into());
+                    }
+
+                    if descriptor.has_local_palette() {
+                        let size = descriptor.local_palette_size();
+                        self.allocate(3 * size as u64)?;
+                        self.read_local_palette(size)?;
+                    }
+
+                    self.state = State::ImageData;
+                    return Ok(Block::ImageDescriptor(
+                        descriptor, None,
                     ));
                 }
 
                 // --- Extension Introducer (0x21)
                 0x21 => {
+                    if self.is_strict() && self.version == Version::Gif87a {
+                        return Err(ErrorKind::ExtensionInGif87a.into());
+                    }
+
                     let mut label = [0u8; 1];
                     self.reader.read_exact(&mut label)?;
 
-                    match label[0] {
+                    return match label[0] {
                         // Graphic Control Extension (0xF9)
-                        0xF9 => {
-                            current_graphic_control = Some(
-                                self.read_graphic_control_ext()?,
-                            );
-                        }
+                        0xF9 => self
+                            .read_graphic_control_ext()
+                            .map(Block::GraphicControl),
                         // Application Extension (0xFF) - e.g. Netscape Loop
-                        0xFF => {
-                            // FIXME: we ignore it for now
-                            self.skip_extension_blocks()?;
+                        0xFF => self.read_application_ext(),
+                        // Comment Extension (0xFE)
+                        0xFE => {
+                            let data = self.read_sub_blocks()?;
+                            self.keep_comment(data);
+                            Ok(Block::Comment(Vec::new()))
                         }
-                        // FIXME: Skip Comment (0xFE) or Text (0x01)
-                        _ => {
-                            self.skip_extension_blocks()?;
+                        // Plain Text Extension (0x01)
+                        0x01 => {
+                            self.read_plain_text_ext().map(Block::PlainText)
                         }
-                    }
+                        label => Ok(Block::Unknown {
+                            label,
+                            data: self.read_sub_blocks()?,
+                        }),
+                    };
                 }
 
                 // --- Trailer (0x3B) ---
-                0x3B => return Ok(Block::Trailer),
+                0x3B => {
+                    self.state = State::Done;
+                    return Ok(Block::Trailer);
+                }
 
                 // --- Padding ---
                 0x00 => continue,
 
                 _ => {
-                    return Err(DecodingError::Format(format!(
-                        "Unknown block introducer: {:#04X}",
-                        introducer[0]
-                    )));
+                    return Err(
+                        ErrorKind::UnknownBlock(introducer[0]).into()
+                    );
                 }
             }
         }
@@ -120,10 +504,14 @@ impl<R: Read> Decoder<R> {
 
     /// Obtains a reader with the current frame compressed data
     ///
-    /// It must be called right after getting `Block::Image` and eventually read the Local Palette.
+    /// It must be called right after getting `Block::ImageDescriptor`,
+    /// the caller is responsible to read it to the end.
     ///
     /// The first byte read from this reader will be `LZW Minimum Code Size`
-    pub fn lzw_reader(&mut self) -> SubBlockReader<'_, R> {
+    pub fn lzw_reader(&mut self) -> SubBlockReader<'_, PositionReader<R>> {
+        if self.state == State::ImageData {
+            self.state = State::Blocks;
+        }
         SubBlockReader::new(&mut self.reader)
     }
 
@@ -134,218 +522,3004 @@ impl<R: Read> Decoder<R> {
         &mut self,
         descriptor: &ImageDescriptor,
         output_buffer: &mut [u8],
-    ) -> Result<(), DecodingError> {
+    ) -> Result<bool, DecodingError> {
+        self.check_frame_pixels(descriptor.width, descriptor.height)?;
+        let expected_pixels =
+            descriptor.width as usize * descriptor.height as usize;
+
+        let decoded = self.decode_indices(descriptor, output_buffer)?;
+        Ok(decoded == expected_pixels)
+    }
+
+    /// Decodes the frame row by row, passing the indices of each row to
+    /// `sink` as soon as it is complete
+    ///
+    /// Interlaced rows come in file order, `RowSink::row` receives
+    /// their destination row. Only a single row is buffered. The last
+    /// row of an incomplete frame can be shorter than the frame.
+    /// Returns true if the image is completely decoded
+    pub fn decode_frame_rows(
+        &mut self,
+        descriptor: &ImageDescriptor,
+        sink: &mut impl RowSink,
+    ) -> Result<bool, DecodingError> {
+        self.check_frame_pixels(descriptor.width, descriptor.height)?;
+        let width = descriptor.width as usize;
+        let expected_pixels = width * descriptor.height as usize;
+
+        let mut row = mem::take(&mut self.row_buffer);
+        row.resize(width, 0);
+
+        let result = self.decode_rows(
+            descriptor,
+            &mut SinkRows {
+                row: &mut row,
+                sink,
+            },
+        );
+        self.row_buffer = row;
+
+        Ok(result? == expected_pixels)
+    }
+
+    /// Decodes the frame indices and returns how many pixels have been
+    /// decoded, in the order they are stored
+    ///
+    /// In lenient mode corrupt or truncated data stops the decoding
+    /// without an error.
+    fn decode_indices(
+        &mut self,
+        descriptor: &ImageDescriptor,
+        output_buffer: &mut [u8],
+    ) -> Result<usize, DecodingError> {
         let width = descriptor.width as usize;
         let height = descriptor.height as usize;
         let expected_pixels = width * height;
 
         if output_buffer.len() < expected_pixels {
-            return Err(DecodingError::Format(format!(
-                "Buffer too small. Requested {} bytes, received {}",
-                expected_pixels,
-                output_buffer.len(),
-            )));
+            return Err(ErrorKind::InvalidBuffer {
+                required: expected_pixels,
+                len: output_buffer.len(),
+            }
+            .into());
+        }
+
+        self.decode_rows(
+            descriptor,
+            &mut IndexRows {
+                buffer: output_buffer,
+                width,
+            },
+        )
+    }
+
+    /// Decodes the frame indices row by row into `target`, see
+    /// `decode_indices`
+    pub(crate) fn decode_rows(
+        &mut self,
+        descriptor: &ImageDescriptor,
+        target: &mut impl RowTarget,
+    ) -> Result<usize, DecodingError> {
+        if self.state != State::ImageData {
+            return Err(ErrorKind::NoImageData.into());
         }
+        self.state = State::Blocks;
+        self.begin_image_data();
+
+        self.read_image_rows(descriptor, target)
+            .map_err(|e| self.locate(e))
+    }
+
+    fn read_image_rows(
+        &mut self,
+        descriptor: &ImageDescriptor,
+        target: &mut impl RowTarget,
+    ) -> Result<usize, DecodingError> {
+        let lenient = self.options.mode == DecodeMode::Lenient;
 
         let mut min_code_size_buf = [0u8; 1];
-        self.reader.read_exact(&mut min_code_size_buf)?;
+        match self.reader.read_exact(&mut min_code_size_buf) {
+            Ok(()) => {}
+            Err(_) if lenient => return Ok(0),
+            Err(e) => return Err(e.into()),
+        }
         let min_code_size = min_code_size_buf[0];
+        self.check_min_code_size(min_code_size)?;
 
-        let mut sub_reader = SubBlockReader::new(&mut self.reader);
-        let mut lzw = LzwDecoder::new(&mut sub_reader, min_code_size);
+        let mode = self.options.mode;
+        let tables = &mut **self.lzw_tables.get_or_insert_default();
 
-        let is_interlaced = descriptor.is_interlaced();
-
-        let (mut pass, mut y, mut checked_pass_advance) =
-            (0, 0, false);
-        let pass_starts = [0, 4, 2, 1];
-        let pass_steps = [8, 8, 4, 2];
+        let (decoded, excess, result) = match self.slice_input {
+            Some(input) => {
+                let data = (input.remaining)(self.reader.get_ref());
+                let mut blocks = SubBlocks::new(data);
+                let (decoded, excess) = decode_image_data(
+                    LzwDecoder::with_tables(
+                        &mut blocks,
+                        min_code_size,
+                        tables,
+                    ),
+                    descriptor,
+                    mode,
+                    target,
+                )?;
 
-        if !is_interlaced {
-            let mut offset = 0;
-            while offset < expected_pixels {
-                let n = lzw.decode_bytes(
-                    &mut output_buffer[offset..expected_pixels],
+                let result = blocks.consume_to_end();
+                let consumed = blocks.position();
+                self.reader.consume_with(consumed, input.advance);
+                (decoded, excess, result)
+            }
+            None => {
+                let mut blocks =
+                    SubBlockBufReader::new(&mut self.reader);
+                let (decoded, excess) = decode_image_data(
+                    LzwDecoder::with_tables(
+                        &mut blocks,
+                        min_code_size,
+                        tables,
+                    ),
+                    descriptor,
+                    mode,
+                    target,
                 )?;
-                if n == 0 {
-                    break;
-                }
-                offset += n;
+
+                (decoded, excess, blocks.consume_to_end())
             }
-        } else {
-            let mut lzw_chunk = [0u8; 1024];
-            let mut x = 0;
+        };
 
-            loop {
-                let n = lzw.decode_bytes(&mut lzw_chunk)?;
-                if n == 0 {
-                    break;
+        // Same rules as `ImageJob::decode` for the frames read ahead:
+        // a truncated sub-block fails the frame, without its warnings
+        match result {
+            Err(e) if !lenient => Err(DecodingError::image_data(e)),
+            _ => {
+                if excess {
+                    self.warn(WarningKind::ExcessImageData);
                 }
+                Ok(decoded)
+            }
+        }
+    }
 
-                for &pixel in &lzw_chunk[..n] {
-                    if y >= height {
-                        break;
-                    }
+    pub fn next_frame(&mut self) -> Result<Option<Frame>, DecodingError> {
+        self.next_frame_as()
+    }
 
-                    let output_idx = y * width + x;
-                    if output_idx < output_buffer.len() {
-                        output_buffer[output_idx] = pixel;
-                    }
+    /// Same as `next_frame`, with pixels in the format `P`
+    pub fn next_frame_as<P: Pixel>(
+        &mut self,
+    ) -> Result<Option<Frame<P>>, DecodingError> {
+        if let Some(grid) = self.scale_grid() {
+            return match self.next_scaled_frame(&grid)? {
+                Some((info, scaled)) => {
+                    self.scaled_to_frame(&grid, info, scaled).map(Some)
+                }
+                None => Ok(None),
+            };
+        }
 
-                    x += 1;
+        let Some((frame, progress)) = self.next_frame_indices(Vec::new())?
+        else {
+            return Ok(None);
+        };
 
-                    if x == width {
-                        x = 0;
-                        y += pass_steps[pass];
+        let mut frame = self.expand_frame(frame)?;
 
-                        while y >= height && pass < 3 {
-                            pass += 1;
-                            y = pass_starts[pass];
-                        }
-                    }
-                }
-            }
+        if !frame.complete && self.options.mode == DecodeMode::Lenient {
+            render::fill_incomplete(
+                &mut frame.pixels,
+                &progress,
+                self.options.incomplete_fill,
+                P::transparent(),
+            );
         }
 
-        drop(lzw);
-        sub_reader.consume_to_end()?;
-
-        Ok(())
+        Ok(Some(frame))
     }
 
-    pub fn next_frame(&mut self) -> Result<Option<Frame>, DecodingError> {
-        let (descriptor, control_ext) = match self.next_record()? {
-            Block::Image(desc, ext) => (desc, ext),
-            Block::Trailer => return Ok(None),
-            Block::Extension => return self.next_frame(),
-        };
+    /// Returns the next frame as color indices and the palette they
+    /// refer to, without expanding them to colors
+    ///
+    /// Frames using the Global palette share it, see
+    /// `IndexedFrame::palette`. In lenient mode, the missing pixels of
+    /// an incomplete frame are set to its transparent index, or to 0
+    /// if it has none.
+    pub fn next_indexed_frame(
+        &mut self,
+    ) -> Result<Option<IndexedFrame>, DecodingError> {
+        self.check_unscaled()?;
 
-        let active_palette: Palette = if descriptor.has_local_palette() {
-            let size = descriptor.local_palette_size();
-            Self::read_palette(&mut self.reader, size)?
-        } else {
-            match &self.global_palette {
-                Some(p) => p.clone(),
-                None => return Err(DecodingError::Format("No Global or Local palette found".into())),
-            }
+        let Some((mut frame, progress)) =
+            self.next_frame_indices(Vec::new())?
+        else {
+            return Ok(None);
         };
 
-        let pixel_count = (descriptor.width as usize) * (descriptor.height as usize);
+        if !frame.complete && self.options.mode == DecodeMode::Lenient {
+            let transparent = frame.transparent_index.unwrap_or(0);
+            render::fill_incomplete(
+                &mut frame.indices,
+                &progress,
+                self.options.incomplete_fill,
+                transparent,
+            );
+        }
 
-        let mut index_buffer = vec![0u8; pixel_count];
+        Ok(Some(frame))
+    }
 
-        let mut rgba_buffer = vec![GifColor::transparent(); pixel_count];
+    /// Decodes the indices of the next frame, cropped to the region of
+    /// interest
+    pub(crate) fn next_frame_indices(
+        &mut self,
+        mut index_buffer: Vec<u8>,
+    ) -> Result<Option<(IndexedFrame, FrameProgress)>, DecodingError>
+    {
+        self.pending_location = None;
+        if self.pending.is_empty() && self.options.threads > 1 {
+            self.decode_ahead(self.options.threads);
+        }
 
-        self.decode_frame_into(&descriptor, &mut index_buffer)?;
+        if let Some(frame) = self.pending.pop_front() {
+            self.pending_location = Some(frame.location);
+            self.warnings.extend(frame.warnings);
+            return frame.result;
+        }
+        if self.options.threads > 1 {
+            return Ok(None);
+        }
 
-        GifColor::map_indices_to_rgba(
-            &index_buffer,
-            &active_palette,
-            &control_ext,
-            &mut rgba_buffer
-        )?;
+        let (descriptor, local_palette, control_ext) =
+            match self.next_frame_start()? {
+                Some(FrameStart::Image(desc, palette, control_ext)) => {
+                    (desc, palette, control_ext)
+                }
+                Some(FrameStart::PlainText(text, control_ext)) => {
+                    return self
+                        .cropped_plain_text_frame(&text, control_ext)
+                        .map(Some);
+                }
+                None => return Ok(None),
+            };
 
-        let delay_cs = control_ext.map(|x| x.delay_time_cs).unwrap_or(0);
-        let disposal = control_ext.map(|x| x.disposal_method).unwrap_or_default();
-        let transparent_idx = control_ext.and_then(|x| x.transparent_color_index);
+        let crop = self.frame_crop(&descriptor);
+        let pixel_count = crop.width as usize * crop.height as usize;
 
-        Ok(Some(Frame {
-            delay_cs,
-            disposal,
-            left: descriptor.left,
-            top: descriptor.top,
-            width: descriptor.width,
-            height: descriptor.height,
-            pixels: rgba_buffer,
-            transparent_index: transparent_idx,
-        }))
+        self.allocate_frame(descriptor.width, descriptor.height, 0)?;
+        self.allocate(pixel_count as u64)?;
+        index_buffer.clear();
+        index_buffer.resize(pixel_count, 0);
+
+        let decoded = if crop_is_whole(&crop, &descriptor) {
+            self.decode_indices(&descriptor, &mut index_buffer)?
+        } else {
+            let mut row = mem::take(&mut self.row_buffer);
+            row.resize(descriptor.width as usize, 0);
+
+            let result = self.decode_rows(
+                &descriptor,
+                &mut CropRows {
+                    row: &mut row,
+                    buffer: &mut index_buffer,
+                    crop,
+                },
+            );
+            self.row_buffer = row;
+            result?
+        };
+
+        self.decoded_frame(
+            &descriptor,
+            crop,
+            decoded,
+            local_palette,
+            control_ext,
+            index_buffer,
+        )
+        .map(Some)
     }
 
-    pub fn into_stream(self) -> Result<GifStream<R>, DecodingError> {
-        GifStream::new(self)
+    /// Pairs the indices decoded from a frame, cropped to `crop`, with
+    /// its palette and progress
+    fn decoded_frame(
+        &mut self,
+        descriptor: &ImageDescriptor,
+        crop: Region,
+        decoded: usize,
+        local_palette: Option<Arc<Palette>>,
+        control_ext: Option<GraphicControl>,
+        index_buffer: Vec<u8>,
+    ) -> Result<(IndexedFrame, FrameProgress), DecodingError> {
+        let progress = FrameProgress {
+            width: descriptor.width as usize,
+            height: descriptor.height as usize,
+            interlaced: descriptor.is_interlaced(),
+            decoded,
+            crop,
+        };
+
+        let mut frame = self.indexed_frame(
+            &cropped_descriptor(descriptor, &crop),
+            local_palette,
+            control_ext,
+            index_buffer,
+        )?;
+        frame.complete = progress.is_complete();
+
+        Ok((frame, progress))
     }
 
-    fn read_palette(
-        reader: &mut R,
-        size: usize,
-    ) -> Result<Palette, DecodingError> {
-        // TOOD: use a buffer pool
-        let mut buffer = vec![0u8; 3];
-        reader.read_exact(&mut buffer)?;
+    /// Reads the compressed data of the next frames, decompresses them
+    /// on `threads` threads and queues them in `pending`
+    fn decode_ahead(&mut self, threads: usize) {
+        let mut frames = Vec::new();
+        let mut jobs = Vec::new();
+        let mut end = None;
 
-        let mut palette = Vec::with_capacity(size);
-        for chunk in buffer.chunks_exact(3) {
-            palette.push(Color {
-                r: chunk[0],
-                g: chunk[1],
-                b: chunk[2],
+        while jobs.len() < threads * FRAMES_PER_THREAD {
+            // The warnings are held back with the frame they belong to
+            let found = self.warnings.len();
+            let result = self.read_frame_ahead(&mut jobs);
+            let warnings = self.warnings.split_off(found);
+            match result {
+                Ok(Some(frame)) => {
+                    frames.push((frame, self.location(), warnings))
+                }
+                Ok(None) => {
+                    end = Some((Ok(None), warnings));
+                    break;
+                }
+                Err(e) => {
+                    end = Some((Err(self.locate(e)), warnings));
+                    break;
+                }
+            }
+        }
+
+        let mut images = self
+            .workers
+            .decode(
+                jobs,
+                self.options.mode,
+                self.lzw_tables.get_or_insert_default(),
+            )
+            .into_iter();
+
+        for (frame, mut location, mut warnings) in frames {
+            #[cfg(feature = "std")]
+            let image = matches!(frame, AheadFrame::Image(..));
+            let result = match frame {
+                AheadFrame::Ready(frame, progress) => Ok((frame, progress)),
+                AheadFrame::Image(local_palette, control_ext) => {
+                    let (job, output) =
+                        images.next().expect("one job per image");
+                    location = (job.offset, Some(job.frame));
+
+                    // Warnings are located where the frame was read
+                    let found = self.warnings.len();
+                    self.pending_location = Some(location);
+                    let result = output.and_then(
+                        |(indices, decoded, excess)| {
+                            if excess {
+                                self.warn(
+                                    WarningKind::ExcessImageData,
+                                );
+                            }
+                            self.decoded_frame(
+                                &job.descriptor,
+                                job.crop,
+                                decoded,
+                                local_palette,
+                                control_ext,
+                                indices,
+                            )
+                        },
+                    );
+                    self.pending_location = None;
+                    warnings.extend(self.warnings.drain(found..));
+                    result
+                }
+            };
+            let result = result.map_err(|e| e.at(location.0, location.1));
+
+            let failed = result.is_err();
+            self.pending.push_back(PendingFrame {
+                result: result.map(Some),
+                location,
+                #[cfg(feature = "std")]
+                image,
+                warnings,
+            });
+            if failed {
+                return;
+            }
+        }
+
+        if let Some((result, warnings)) = end {
+            self.pending.push_back(PendingFrame {
+                result,
+                location: self.location(),
+                #[cfg(feature = "std")]
+                image: false,
+                warnings,
             });
         }
-        Ok(palette)
     }
 
-    fn read_image_descriptor(
+    /// Reads the next frame, adding its compressed data to `jobs` if
+    /// it is an image
+    fn read_frame_ahead(
         &mut self,
-    ) -> Result<ImageDescriptor, DecodingError> {
-        let mut buf = [0u8; 9];
-        self.reader.read_exact(&mut buf)?;
+        jobs: &mut Vec<ImageJob>,
+    ) -> Result<Option<AheadFrame>, DecodingError> {
+        let (descriptor, local_palette, control_ext) =
+            match self.next_frame_start()? {
+                Some(FrameStart::Image(desc, palette, control_ext)) => {
+                    (desc, palette, control_ext)
+                }
+                Some(FrameStart::PlainText(text, control_ext)) => {
+                    let (frame, progress) =
+                        self.cropped_plain_text_frame(&text, control_ext)?;
+                    return Ok(Some(AheadFrame::Ready(frame, progress)));
+                }
+                None => return Ok(None),
+            };
 
-        Ok(ImageDescriptor {
-            left: u16::from_le_bytes([buf[0], buf[1]]),
-            top: u16::from_le_bytes([buf[2], buf[3]]),
-            width: u16::from_le_bytes([buf[4], buf[5]]),
-            height: u16::from_le_bytes([buf[6], buf[7]]),
-            packed: buf[8],
-        })
+        let crop = self.frame_crop(&descriptor);
+        let pixel_count = crop.width as u64 * crop.height as u64;
+        self.allocate_frame(descriptor.width, descriptor.height, 0)?;
+        self.allocate(pixel_count)?;
+
+        jobs.push(self.read_image_job(descriptor, crop)?);
+        Ok(Some(AheadFrame::Image(local_palette, control_ext)))
     }
 
-    fn read_graphic_control_ext(
+    /// Reads the LZW Minimum Code Size and the content of the image
+    /// data sub-blocks, to be decompressed later
+    fn read_image_job(
         &mut self,
-    ) -> Result<GraphicControl, DecodingError> {
-        // [Block Size = 4] [Packed] [Delay L] [Delay H] [Trans Index] [Terminator = 0]
-        let mut buf = [0u8; 6];
-        self.reader.read_exact(&mut buf)?;
-
-        if buf[0] != 4 {
-            return Err(DecodingError::Format(
-                "Invalid GCE size".into(),
-            ));
+        descriptor: ImageDescriptor,
+        crop: Region,
+    ) -> Result<ImageJob, DecodingError> {
+        if self.state != State::ImageData {
+            return Err(ErrorKind::NoImageData.into());
         }
+        self.state = State::Blocks;
+        self.begin_image_data();
 
-        let packed = buf[1];
-        let disposal = (packed & 0b0001_1100) >> 2;
-        let has_transparency = (packed & 1) != 0;
-
-        let delay = u16::from_le_bytes([buf[2], buf[3]]);
-        let trans_index =
-            if has_transparency { Some(buf[4]) } else { None };
+        let mut job = ImageJob {
+            descriptor,
+            crop,
+            offset: self.block_offset,
+            frame: self.image_count - 1,
+            min_code_size: None,
+            data: Vec::new(),
+            truncated: false,
+        };
 
-        Ok(GraphicControl {
-            disposal_method: DisposalMethod::from(disposal),
-            user_input_flag: (packed & 0b0000_0010) != 0,
-            delay_time_cs: delay,
-            transparent_color_index: trans_index,
-        })
-    }
+        let mut min_code_size_buf = [0u8; 1];
+        match self.reader.read_exact(&mut min_code_size_buf) {
+            Ok(()) => {}
+            Err(_) if self.options.mode == DecodeMode::Lenient => {
+                return Ok(job);
+            }
+            Err(e) => return Err(e.into()),
+        }
+        self.check_min_code_size(min_code_size_buf[0])?;
+        job.min_code_size = Some(min_code_size_buf[0]);
 
-    /// GIF metadata are divided in blocks: [Length N] [N Bytes] ... [0 (Terminator)]
-    fn skip_extension_blocks(&mut self) -> Result<(), DecodingError> {
-        let mut len_buf = [0u8; 1];
+        let mut block = [0u8; 255];
         loop {
-            self.reader.read_exact(&mut len_buf)?;
+            // The data ends at a missing terminator, as for
+            // `SubBlockReader`
+            let mut len_buf = [0u8; 1];
+            if self.reader.read(&mut len_buf)? == 0 || len_buf[0] == 0 {
+                break;
+            }
 
             let len = len_buf[0] as usize;
-            if len == 0 {
-                break; // Terminator found
+            let mut filled = 0;
+            while filled < len {
+                match self.reader.read(&mut block[filled..len])? {
+                    0 => break,
+                    n => filled += n,
+                }
             }
 
-            // Skip N bytes
-            // We can improve by using std::io::skip if the Decoder will support std::io::Seek in a future
-            // TODO: use a static buffer
-            let mut temp = vec![0u8; len];
-            self.reader.read_exact(&mut temp)?;
+            self.allocate(filled as u64)?;
+            job.data.extend_from_slice(&block[..filled]);
+
+            if filled < len {
+                job.truncated = true;
+                break;
+            }
         }
 
-        Ok(())
+        Ok(job)
+    }
+
+    /// Returns the part of a frame inside the region of interest, in
+    /// frame coordinates
+    fn frame_crop(&self, descriptor: &ImageDescriptor) -> Region {
+        let frame = Region::new(
+            descriptor.left,
+            descriptor.top,
+            descriptor.width,
+            descriptor.height,
+        );
+
+        match &self.options.region {
+            Some(region) => {
+                let visible = frame.intersect(region);
+                Region::new(
+                    visible.left - frame.left,
+                    visible.top - frame.top,
+                    visible.width,
+                    visible.height,
+                )
+            }
+            None => Region::new(0, 0, frame.width, frame.height),
+        }
+    }
+
+    fn cropped_plain_text_frame(
+        &mut self,
+        text: &PlainText,
+        control_ext: Option<GraphicControl>,
+    ) -> Result<(IndexedFrame, FrameProgress), DecodingError> {
+        let mut frame = self.plain_text_frame(text, control_ext)?;
+        let descriptor = ImageDescriptor {
+            left: text.left,
+            top: text.top,
+            width: text.width,
+            height: text.height,
+            packed: 0,
+        };
+
+        let crop = self.frame_crop(&descriptor);
+        let progress = FrameProgress {
+            width: text.width as usize,
+            height: text.height as usize,
+            interlaced: false,
+            decoded: frame.indices.len(),
+            crop,
+        };
+
+        if !crop_is_whole(&crop, &descriptor) {
+            let width = text.width as usize;
+            let left = crop.left as usize;
+            let top = crop.top as usize;
+
+            frame.indices = frame
+                .indices
+                .chunks_exact(width)
+                .skip(top)
+                .take(crop.height as usize)
+                .flat_map(|row| &row[left..left + crop.width as usize])
+                .copied()
+                .collect();
+
+            let cropped = cropped_descriptor(&descriptor, &crop);
+            frame.left = cropped.left;
+            frame.top = cropped.top;
+            frame.width = cropped.width;
+            frame.height = cropped.height;
+        }
+
+        Ok((frame, progress))
+    }
+
+    /// Reads the blocks up to the next graphic rendering block
+    fn next_frame_start(
+        &mut self,
+    ) -> Result<Option<FrameStart>, DecodingError> {
+        let mut control_ext = None;
+
+        loop {
+            match self.next_block()? {
+                Block::GraphicControl(ext) => control_ext = Some(ext),
+                Block::ImageDescriptor(desc, _) => {
+                    let palette = desc
+                        .has_local_palette()
+                        .then(|| Arc::clone(&self.local_palette));
+                    return Ok(Some(FrameStart::Image(
+                        desc,
+                        palette,
+                        control_ext,
+                    )));
+                }
+                Block::PlainText(text) if self.render_plain_text => {
+                    return Ok(Some(FrameStart::PlainText(
+                        text,
+                        control_ext,
+                    )));
+                }
+                // A Graphic Control Extension only applies to the
+                // graphic rendering block that follows it
+                Block::PlainText(_) => control_ext = None,
+                Block::Trailer => return Ok(None),
+                _ => continue,
+            }
+        }
+    }
+
+    /// Decodes the next frame into `buffer`, with pixels in the format
+    /// `P` placed according to `layout`
+    ///
+    /// Rows are decoded one at a time, no frame sized buffer is
+    /// allocated. Only the frame rectangle, cropped to the region of
+    /// interest, is written, see `GifStream::next_into` for composited
+    /// canvases.
+    pub fn next_frame_into<P: Pixel>(
+        &mut self,
+        buffer: &mut [u8],
+        layout: BufferLayout,
+    ) -> Result<Option<FrameInfo>, DecodingError> {
+        self.check_unscaled()?;
+
+        let (descriptor, local_palette, control_ext) =
+            match self.next_frame_start()? {
+                Some(FrameStart::Image(desc, palette, control_ext)) => {
+                    (desc, palette, control_ext)
+                }
+                Some(FrameStart::PlainText(text, control_ext)) => {
+                    let (frame, _) =
+                        self.cropped_plain_text_frame(&text, control_ext)?;
+                    let width = frame.width as usize;
+                    let height = frame.height as usize;
+                    layout.check::<P>(width, height, buffer.len())?;
+
+                    let table = pixel::lookup_table::<P>(
+                        &frame.palette,
+                        frame.transparent_index,
+                    );
+                    for y in 0..height {
+                        let out = layout.row::<P>(buffer, y, width);
+                        let indices = &frame.indices[y * width..][..width];
+                        for (bytes, &index) in
+                            out.chunks_exact_mut(P::SIZE).zip(indices)
+                        {
+                            table[index as usize].write_bytes(bytes);
+                        }
+                    }
+
+                    return Ok(Some(frame.info()));
+                }
+                None => return Ok(None),
+            };
+
+        let crop = self.frame_crop(&descriptor);
+        let left = crop.left as usize;
+        let top = crop.top as usize;
+        let width = crop.width as usize;
+        let height = crop.height as usize;
+        layout.check::<P>(width, height, buffer.len())?;
+        self.allocate_frame(descriptor.width, descriptor.height, 0)?;
+
+        let palette = self.active_palette(local_palette)?;
+        let transparent_index =
+            control_ext.and_then(|x| x.transparent_color_index);
+
+        let mut row = mem::take(&mut self.row_buffer);
+        row.resize(descriptor.width as usize, 0);
+
+        let mut target = PixelRows {
+            row: &mut row,
+            table: pixel::lookup_table::<P>(&palette, transparent_index),
+            palette_len: palette.len(),
+            transparent_index,
+            check_indices: palette.len() < 256,
+            invalid_index: None,
+            buffer: &mut *buffer,
+            layout,
+            crop,
+        };
+        let result = self.decode_rows(&descriptor, &mut target);
+        let invalid_index = target.invalid_index;
+        self.row_buffer = row;
+
+        let decoded = result?;
+        if let Some(index) = invalid_index {
+            self.invalid_index(index, palette.len())?;
+        }
+
+        let progress = FrameProgress {
+            width: descriptor.width as usize,
+            height: descriptor.height as usize,
+            interlaced: descriptor.is_interlaced(),
+            decoded,
+            crop,
+        };
+
+        let complete = progress.is_complete();
+        if !complete && self.options.mode == DecodeMode::Lenient {
+            let fill = self.options.incomplete_fill;
+            render::incomplete_rows(&progress, |y, done, src| {
+                if y < top || y >= top + height {
+                    return;
+                }
+
+                let row_start = |y: usize| {
+                    layout.offset + (y - top) * layout.stride
+                };
+                let dst = row_start(y);
+                let start = done.max(left).min(left + width) - left;
+                let start = start * P::SIZE;
+                let end = width * P::SIZE;
+
+                match (fill, src) {
+                    // Rows above the crop are not kept
+                    (IncompleteFill::LastRow, Some(src)) if src >= top => {
+                        let src = row_start(src);
+                        buffer.copy_within(
+                            src + start..src + end,
+                            dst + start,
+                        );
+                    }
+                    _ => {
+                        for bytes in buffer[dst + start..dst + end]
+                            .chunks_exact_mut(P::SIZE)
+                        {
+                            P::transparent().write_bytes(bytes);
+                        }
+                    }
+                }
+            });
+        }
+
+        Ok(Some(frame_info(
+            &cropped_descriptor(&descriptor, &crop),
+            control_ext,
+            complete,
+        )))
+    }
+
+    /// Returns the grid of scaled decoding, if it is enabled
+    pub(crate) fn scale_grid(&self) -> Option<ScaleGrid> {
+        if self.options.scale == Scale::Full {
+            return None;
+        }
+
+        let screen = Region::new(
+            0,
+            0,
+            self.screen_descriptor.width,
+            self.screen_descriptor.height,
+        );
+        let bounds = match &self.options.region {
+            Some(region) => screen.intersect(region),
+            None => screen,
+        };
+
+        Some(ScaleGrid::new(
+            self.options.scale.factor(),
+            self.options.scale_filter,
+            bounds,
+        ))
+    }
+
+    /// Decodes the next frame scaled on `grid`
+    /// The returned info describes the frame on the screen.
+    pub(crate) fn next_scaled_frame(
+        &mut self,
+        grid: &ScaleGrid,
+    ) -> Result<Option<(FrameInfo, ScaledFrame)>, DecodingError> {
+        let (descriptor, local_palette, control_ext) =
+            match self.next_frame_start()? {
+                Some(FrameStart::Image(desc, palette, control_ext)) => {
+                    (desc, palette, control_ext)
+                }
+                Some(FrameStart::PlainText(text, control_ext)) => {
+                    let frame = self.plain_text_frame(&text, control_ext)?;
+                    let rect = Region::new(
+                        frame.left,
+                        frame.top,
+                        frame.width,
+                        frame.height,
+                    );
+                    self.allocate(ScaledFrame::allocation(grid, rect))?;
+
+                    let mut scaled = ScaledFrame::new(grid, rect);
+                    let table = scale::color_table(
+                        &frame.palette,
+                        frame.transparent_index,
+                    );
+                    let width = frame.width as usize;
+                    for (i, &index) in frame.indices.iter().enumerate() {
+                        if let Some(color) = table[index as usize] {
+                            let x = frame.left as usize + i % width;
+                            let y = frame.top as usize + i / width;
+                            scaled.add(grid, x, y, color);
+                        }
+                    }
+
+                    return Ok(Some((frame.info(), scaled)));
+                }
+                None => return Ok(None),
+            };
+
+        let rect = Region::new(
+            descriptor.left,
+            descriptor.top,
+            descriptor.width,
+            descriptor.height,
+        );
+        self.allocate_frame(descriptor.width, descriptor.height, 0)?;
+        self.allocate(ScaledFrame::allocation(grid, rect))?;
+
+        let palette = self.active_palette(local_palette)?;
+        let transparent_index =
+            control_ext.and_then(|x| x.transparent_color_index);
+
+        let mut scaled = ScaledFrame::new(grid, rect);
+        let mut row = mem::take(&mut self.row_buffer);
+        row.resize(descriptor.width as usize, 0);
+
+        let mut target = ScaleRows {
+            row: &mut row,
+            table: scale::color_table(&palette, transparent_index),
+            palette_len: palette.len(),
+            transparent_index,
+            invalid_index: None,
+            grid: *grid,
+            left: descriptor.left as usize,
+            top: descriptor.top as usize,
+            frame: &mut scaled,
+        };
+        let result = self.decode_rows(&descriptor, &mut target);
+        let invalid_index = target.invalid_index;
+        self.row_buffer = row;
+
+        let decoded = result?;
+        if let Some(index) = invalid_index {
+            self.invalid_index(index, palette.len())?;
+        }
+        let complete = decoded
+            == descriptor.width as usize * descriptor.height as usize;
+
+        Ok(Some((
+            frame_info(&descriptor, control_ext, complete),
+            scaled,
+        )))
+    }
+
+    /// Converts a scaled frame to pixels `P`
+    fn scaled_to_frame<P: Pixel>(
+        &mut self,
+        grid: &ScaleGrid,
+        info: FrameInfo,
+        scaled: ScaledFrame,
+    ) -> Result<Frame<P>, DecodingError> {
+        let rect = Region::new(info.left, info.top, info.width, info.height);
+        let pixel_count = scaled.width * scaled.height;
+
+        self.allocate((mem::size_of::<P>() * pixel_count) as u64)?;
+        let pixels = (0..pixel_count)
+            .map(|i| P::from_rgba(scaled.pixel(grid, rect, i)))
+            .collect();
+
+        Ok(Frame {
+            delay_cs: info.delay_cs,
+            disposal: info.disposal,
+            left: scaled.left as u16,
+            top: scaled.top as u16,
+            width: scaled.width as u16,
+            height: scaled.height as u16,
+            pixels,
+            transparent_index: info.transparent_index,
+            complete: info.complete,
+        })
+    }
+
+    fn check_unscaled(&self) -> Result<(), DecodingError> {
+        if self.options.scale == Scale::Full {
+            Ok(())
+        } else {
+            Err(ErrorKind::Unsupported(
+                "Scaled decoding of indexed frames",
+            )
+            .into())
+        }
+    }
+
+    /// Returns the palette a frame is drawn with, converted to sRGB if
+    /// the color profile is applied
+    fn active_palette(
+        &mut self,
+        local_palette: Option<Arc<Palette>>,
+    ) -> Result<Arc<Palette>, DecodingError> {
+        #[cfg(feature = "std")]
+        if let Some(profile) = self
+            .color_profile
+            .as_ref()
+            .filter(|_| self.apply_color_profile)
+        {
+            if let Some(palette) = &local_palette {
+                return Ok(Arc::new(profile.convert_palette(palette)));
+            }
+            if let Some(global) = &self.global_palette {
+                return Ok(Arc::clone(
+                    self.converted_palette.get_or_insert_with(|| {
+                        Arc::new(profile.convert_palette(global))
+                    }),
+                ));
+            }
+        }
+
+        if let Some(palette) = local_palette {
+            return Ok(palette);
+        }
+
+        let Some(global) = &self.global_palette else {
+            return Err(self.locate(
+                ErrorKind::MissingPalette.into(),
+            ));
+        };
+
+        Ok(Arc::clone(global))
+    }
+
+    /// Pairs the decoded indices of a frame with its palette
+    pub(crate) fn indexed_frame(
+        &mut self,
+        descriptor: &ImageDescriptor,
+        local_palette: Option<Arc<Palette>>,
+        control_ext: Option<GraphicControl>,
+        index_buffer: Vec<u8>,
+    ) -> Result<IndexedFrame, DecodingError> {
+        let palette = self.active_palette(local_palette)?;
+        let transparent_idx = control_ext.and_then(|x| x.transparent_color_index);
+
+        if palette.len() < 256
+            && let Some(&index) = index_buffer.iter().find(|&&i| {
+                i as usize >= palette.len() && Some(i) != transparent_idx
+            })
+        {
+            self.invalid_index(index, palette.len())?;
+        }
+
+        let delay_cs = control_ext.map(|x| x.delay_time_cs).unwrap_or(0);
+        let disposal = control_ext.map(|x| x.disposal_method).unwrap_or_default();
+
+        Ok(IndexedFrame {
+            delay_cs,
+            disposal,
+            left: descriptor.left,
+            top: descriptor.top,
+            width: descriptor.width,
+            height: descriptor.height,
+            indices: index_buffer,
+            palette,
+            transparent_index: transparent_idx,
+            complete: true,
+        })
+    }
+
+    /// Maps the indices of a frame to pixels
+    pub(crate) fn expand_frame<P: Pixel>(
+        &mut self,
+        frame: IndexedFrame,
+    ) -> Result<Frame<P>, DecodingError> {
+        let pixel_size = mem::size_of::<P>() as u64;
+        self.allocate(pixel_size * frame.indices.len() as u64)?;
+        let mut pixels = vec![P::transparent(); frame.indices.len()];
+
+        render::map_indices(
+            &frame.indices,
+            &frame.palette,
+            frame.transparent_index,
+            &mut pixels,
+        );
+
+        Ok(Frame {
+            delay_cs: frame.delay_cs,
+            disposal: frame.disposal,
+            left: frame.left,
+            top: frame.top,
+            width: frame.width,
+            height: frame.height,
+            pixels,
+            transparent_index: frame.transparent_index,
+            complete: frame.complete,
+        })
+    }
+
+    pub(crate) fn plain_text_frame(
+        &mut self,
+        text: &PlainText,
+        control_ext: Option<GraphicControl>,
+    ) -> Result<IndexedFrame, DecodingError> {
+        if self.global_palette.is_none() {
+            return Err(self.locate(
+                ErrorKind::MissingPalette.into(),
+            ));
+        }
+
+        self.allocate_frame(text.width, text.height, 1)?;
+
+        let pixel_count = (text.width as usize) * (text.height as usize);
+        let mut index_buffer = vec![0u8; pixel_count];
+
+        render::rasterize_plain_text(text, &mut index_buffer);
+
+        let descriptor = ImageDescriptor {
+            left: text.left,
+            top: text.top,
+            width: text.width,
+            height: text.height,
+            packed: 0,
+        };
+
+        self.indexed_frame(&descriptor, None, control_ext, index_buffer)
+    }
+
+    pub fn into_stream(self) -> Result<GifStream<R>, DecodingError> {
+        GifStream::new(self)
+    }
+
+    /// Same as `into_stream`, with canvases in the format `P`
+    pub fn into_stream_as<P: Pixel>(
+        self,
+    ) -> Result<GifStream<R, P>, DecodingError> {
+        GifStream::with_format(self)
+    }
+
+    pub(crate) fn mode(&self) -> DecodeMode {
+        self.options.mode
+    }
+
+    pub(crate) fn incomplete_fill(&self) -> IncompleteFill {
+        self.options.incomplete_fill
+    }
+
+    pub(crate) fn region(&self) -> Option<Region> {
+        self.options.region
+    }
+
+    fn is_strict(&self) -> bool {
+        self.options.mode == DecodeMode::Strict
+    }
+
+    /// Minimum code sizes above 11 would make codes exceed the 12 bits
+    /// limit, the spec allows 2 to 8
+    pub(crate) fn check_min_code_size(
+        &self,
+        min_code_size: u8,
+    ) -> Result<(), DecodingError> {
+        check_min_code_size(min_code_size, self.options.mode)
+    }
+
+    fn check_image_descriptor(
+        &self,
+        descriptor: &ImageDescriptor,
+    ) -> Result<(), DecodingError> {
+        check_image_descriptor(descriptor, &self.screen_descriptor)
+    }
+
+    fn screen_contains(&self, descriptor: &ImageDescriptor) -> bool {
+        screen_contains(descriptor, &self.screen_descriptor)
+    }
+
+    /// Where the decoder is, see `DecodingError::offset` and
+    /// `DecodingError::frame`
+    fn location(&self) -> Location {
+        self.pending_location.unwrap_or((
+            self.block_offset,
+            self.image_count.checked_sub(1),
+        ))
+    }
+
+    /// Adds where the decoder is to `err`, unless already known
+    pub(crate) fn locate(&self, err: DecodingError) -> DecodingError {
+        let (offset, frame) = self.location();
+        err.at(offset, frame)
+    }
+
+    /// Records a defect of the GIF where the decoder is
+    pub(crate) fn warn(&mut self, kind: WarningKind) {
+        let (offset, frame) = self.location();
+        self.warnings.push(Warning::new(kind, offset, frame));
+    }
+
+    /// Reports an index beyond the palette: an error in strict mode, a
+    /// warning otherwise
+    fn invalid_index(
+        &mut self,
+        index: u8,
+        palette_len: usize,
+    ) -> Result<(), DecodingError> {
+        if self.is_strict() {
+            return Err(self.locate(invalid_index_error(
+                index,
+                palette_len,
+            )));
+        }
+
+        self.warn(WarningKind::InvalidColorIndex {
+            index,
+            palette_len,
+        });
+        Ok(())
+    }
+
+    /// Marks the start of the image data, read as a block of its own
+    pub(crate) fn begin_image_data(&mut self) {
+        self.block_offset = self.reader.position();
+    }
+
+    /// Keeps the comment starting at `block_offset`, in file order,
+    /// unless it has already been read before a seek
+    fn keep_comment(&mut self, data: Vec<u8>) {
+        let offset = self.block_offset;
+        let i = self.comment_offsets.partition_point(|&o| o < offset);
+        if self.comment_offsets.get(i) == Some(&offset) {
+            return;
+        }
+
+        self.comment_offsets.insert(i, offset);
+        let frame_index = self.image_count;
+        self.comments.insert(i, Comment { frame_index, data });
+    }
+
+    /// Number of images returned so far, without the images read
+    /// ahead
+    #[cfg(feature = "std")]
+    pub(crate) fn images_returned(&self) -> usize {
+        let ahead = self.pending.iter().filter(|f| f.image).count();
+        self.image_count - ahead
+    }
+
+    /// Lends the LZW tables to an image decoded over several calls,
+    /// they are given back by `restore_lzw_tables`
+    pub(crate) fn take_lzw_tables(&mut self) -> Box<LzwTables> {
+        self.lzw_tables.take().unwrap_or_default()
+    }
+
+    pub(crate) fn restore_lzw_tables(
+        &mut self,
+        tables: Box<LzwTables>,
+    ) {
+        self.lzw_tables = Some(tables);
+    }
+
+    /// Accounts `bytes` of memory allocated on behalf of the decoder
+    pub(crate) fn allocate(&mut self, bytes: u64) -> Result<(), DecodingError> {
+        self.allocated_bytes = self.allocated_bytes.saturating_add(bytes);
+
+        match self.options.limits.max_total_bytes {
+            Some(max) if self.allocated_bytes > max => Err(self.locate(
+                ErrorKind::LimitExceeded(Limit::TotalBytes).into(),
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    /// Checks the frame size and accounts a buffer of
+    /// `bytes_per_pixel` for each of its pixels
+    pub(crate) fn allocate_frame(
+        &mut self,
+        width: u16,
+        height: u16,
+        bytes_per_pixel: u64,
+    ) -> Result<(), DecodingError> {
+        self.check_frame_pixels(width, height)?;
+        self.allocate(width as u64 * height as u64 * bytes_per_pixel)
+    }
+
+    /// Checks the frame size against `Limits::max_frame_pixels`
+    fn check_frame_pixels(
+        &self,
+        width: u16,
+        height: u16,
+    ) -> Result<(), DecodingError> {
+        let pixels = width as u64 * height as u64;
+
+        match self.options.limits.max_frame_pixels {
+            Some(max) if pixels > max => Err(self.locate(
+                ErrorKind::LimitExceeded(Limit::FramePixels).into(),
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    /// Skips the replay of the header blocks by `next_record`
+    pub(crate) fn skip_header_records(&mut self) {
+        if matches!(
+            self.state,
+            State::Header | State::LogicalScreen | State::GlobalPalette
+        ) {
+            self.state = State::Blocks;
+        }
+    }
+
+    /// Marks the image data as read by the caller through `reader_mut`
+    pub(crate) fn image_data_consumed(&mut self) {
+        if self.state == State::ImageData {
+            self.state = State::Blocks;
+        }
+    }
+
+    pub(crate) fn reader_mut(&mut self) -> &mut PositionReader<R> {
+        &mut self.reader
+    }
+
+    pub(crate) fn renders_plain_text(&self) -> bool {
+        self.render_plain_text
+    }
+
+    /// Reads the `size` colors of a palette into `palette`
+    fn read_palette(
+        reader: &mut impl Read,
+        size: usize,
+        palette: &mut Palette,
+    ) -> Result<(), DecodingError> {
+        let mut buffer = [0u8; 3 * 256];
+        let buffer = &mut buffer[..size * 3];
+        reader.read_exact(buffer)?;
+
+        palette.clear();
+        for chunk in buffer.chunks_exact(3) {
+            palette.push(Color {
+                r: chunk[0],
+                g: chunk[1],
+                b: chunk[2],
+            });
+        }
+        Ok(())
+    }
+
+    /// Reads a Local Palette into `local_palette`, in place unless a
+    /// frame still holds it
+    fn read_local_palette(
+        &mut self,
+        size: usize,
+    ) -> Result<(), DecodingError> {
+        if Arc::get_mut(&mut self.local_palette).is_none() {
+            self.local_palette = Arc::default();
+        }
+        let palette = Arc::get_mut(&mut self.local_palette)
+            .expect("the palette is not shared");
+        palette.clear();
+        palette.reserve(256);
+        Self::read_palette(&mut self.reader, size, palette)
+    }
+
+    fn read_image_descriptor(
+        &mut self,
+    ) -> Result<ImageDescriptor, DecodingError> {
+        read_image_descriptor(&mut self.reader)
+    }
+
+    fn read_graphic_control_ext(
+        &mut self,
+    ) -> Result<GraphicControl, DecodingError> {
+        read_graphic_control_ext(&mut self.reader, self.options.mode)
+    }
+
+    fn read_plain_text_ext(&mut self) -> Result<PlainText, DecodingError> {
+        // [Block Size = 12] [Left (2)] [Top (2)] [Width (2)] [Height (2)]
+        // [Cell W] [Cell H] [FG Index] [BG Index] [Sub-blocks...]
+        let mut buf = [0u8; 13];
+        self.reader.read_exact(&mut buf)?;
+
+        if buf[0] != 12 {
+            return Err(ErrorKind::InvalidExtensionSize.into());
+        }
+
+        Ok(PlainText {
+            left: u16::from_le_bytes([buf[1], buf[2]]),
+            top: u16::from_le_bytes([buf[3], buf[4]]),
+            width: u16::from_le_bytes([buf[5], buf[6]]),
+            height: u16::from_le_bytes([buf[7], buf[8]]),
+            cell_width: buf[9],
+            cell_height: buf[10],
+            fg_color_index: buf[11],
+            bg_color_index: buf[12],
+            text: self.read_sub_blocks()?,
+        })
+    }
+
+    fn read_application_ext(&mut self) -> Result<Block, DecodingError> {
+        // [Block Size = 11] [Identifier (8)] [Auth Code (3)] [Sub-blocks...]
+        let mut header = [0u8; 12];
+        self.reader.read_exact(&mut header)?;
+
+        if header[0] != 11 {
+            return Err(ErrorKind::InvalidExtensionSize.into());
+        }
+
+        let mut identifier = [0u8; 8];
+        let mut auth_code = [0u8; 3];
+        identifier.copy_from_slice(&header[1..9]);
+        auth_code.copy_from_slice(&header[9..12]);
+
+        let data = match &header[1..] {
+            b"XMP DataXMP" => {
+                // XMP is stored as is: the sub-block length bytes are
+                // part of the packet and the magic trailer makes the
+                // blocks self-skip
+                let raw = self.read_sub_blocks_framed()?;
+                let data = unframe_sub_blocks(&raw);
+                self.xmp_packet = Some(strip_xmp_trailer(raw));
+                data
+            }
+            b"NETSCAPE2.0" | b"ANIMEXTS1.0" => {
+                let raw = self.read_sub_blocks_framed()?;
+                self.read_netscape_data(&raw);
+                unframe_sub_blocks(&raw)
+            }
+            _ => self.read_sub_blocks()?,
+        };
+
+        if identifier == ICC_IDENTIFIER
+            && auth_code == ICC_AUTH_CODE
+        {
+            // An unsupported profile is still available as raw bytes
+            #[cfg(feature = "std")]
+            {
+                self.color_profile = IccProfile::parse(&data).ok();
+                self.converted_palette = None;
+            }
+            self.icc_profile = Some(data);
+            return Ok(Block::Application {
+                identifier,
+                auth_code,
+                data: Vec::new(),
+            });
+        }
+
+        Ok(Block::Application {
+            identifier,
+            auth_code,
+            data,
+        })
+    }
+
+    /// NETSCAPE2.0 data sub-blocks start with an ID:
+    /// 1 is followed by the loop count (u16),
+    /// 2 by the buffering size (u32)
+    ///
+    /// `raw` holds the sub-blocks with their length bytes, each one is
+    /// parsed on its own and bytes past its fields are ignored.
+    fn read_netscape_data(&mut self, mut raw: &[u8]) {
+        while let [len, rest @ ..] = raw {
+            let len = (*len as usize).min(rest.len());
+            match &rest[..len] {
+                [1, lo, hi, ..] => {
+                    let n = u16::from_le_bytes([*lo, *hi]);
+                    self.loop_count = Some(LoopCount::from(n));
+                }
+                [2, b0, b1, b2, b3, ..] => {
+                    self.buffer_size =
+                        Some(u32::from_le_bytes([*b0, *b1, *b2, *b3]));
+                }
+                _ => {}
+            }
+            raw = &rest[len..];
+        }
+    }
+
+    /// Skips the LZW Minimum Code Size and the image data sub-blocks
+    fn skip_image_data(&mut self) -> Result<(), DecodingError> {
+        self.begin_image_data();
+
+        let mut min_code_size_buf = [0u8; 1];
+        self.reader.read_exact(&mut min_code_size_buf)?;
+        self.state = State::Blocks;
+
+        if let Some(input) = self.slice_input {
+            let data = (input.remaining)(self.reader.get_ref());
+            let mut blocks = SubBlocks::new(data);
+            let result = blocks.consume_to_end();
+            let (consumed, terminated) =
+                (blocks.position(), blocks.is_terminated());
+            self.reader.consume_with(consumed, input.advance);
+
+            // As `read_sub_block`, the terminator is required
+            result.map_err(DecodingError::image_data)?;
+            if !terminated {
+                return Err(ErrorKind::TruncatedSubBlock.into());
+            }
+            return Ok(());
+        }
+
+        self.skip_extension_blocks()
+    }
+
+    /// Reads every data sub-block up to the terminator and
+    /// concatenates their content
+    fn read_sub_blocks(&mut self) -> Result<Vec<u8>, DecodingError> {
+        let mut data = Vec::new();
+        let mut block = [0u8; 255];
+        loop {
+            let len = self.read_sub_block(&mut block)?;
+            if len == 0 {
+                break;
+            }
+            self.check_extension_len(data.len() + len)?;
+            data.extend_from_slice(&block[..len]);
+        }
+
+        self.allocate(data.len() as u64)?;
+        Ok(data)
+    }
+
+    /// Reads every data sub-block up to the terminator, keeping the
+    /// length bytes. The terminator is not included.
+    fn read_sub_blocks_framed(&mut self) -> Result<Vec<u8>, DecodingError> {
+        let mut data = Vec::new();
+        let mut block = [0u8; 255];
+        loop {
+            let len = self.read_sub_block(&mut block)?;
+            if len == 0 {
+                break;
+            }
+            self.check_extension_len(data.len() + 1 + len)?;
+            data.push(len as u8);
+            data.extend_from_slice(&block[..len]);
+        }
+
+        self.allocate(data.len() as u64)?;
+        Ok(data)
+    }
+
+    fn check_extension_len(&self, len: usize) -> Result<(), DecodingError> {
+        match self.options.limits.max_extension_bytes {
+            Some(max) if len > max => {
+                Err(ErrorKind::LimitExceeded(Limit::ExtensionBytes).into())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Reads a single data sub-block into `buf`
+    /// Returns its length, 0 means the block terminator has been read
+    fn read_sub_block(
+        &mut self,
+        buf: &mut [u8; 255],
+    ) -> Result<usize, DecodingError> {
+        let mut len_buf = [0u8; 1];
+        self.reader.read_exact(&mut len_buf)?;
+
+        let len = len_buf[0] as usize;
+        match self.reader.read_exact(&mut buf[..len]) {
+            Ok(()) => Ok(len),
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => {
+                Err(ErrorKind::TruncatedSubBlock.into())
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// GIF metadata are divided in blocks: [Length N] [N Bytes] ... [0 (Terminator)]
+    fn skip_extension_blocks(&mut self) -> Result<(), DecodingError> {
+        // Skip N bytes
+        // We can improve by using std::io::skip if the Decoder will support std::io::Seek in a future
+        let mut block = [0u8; 255];
+        loop {
+            let len = self.read_sub_block(&mut block)?;
+            if len == 0 {
+                break; // Terminator found
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl<'a> Decoder<&'a [u8]> {
+    /// Creates a decoder for a GIF held in memory
+    ///
+    /// Same as `new`, but the image data is decompressed or skipped in
+    /// place, without copying it.
+    pub fn from_slice(data: &'a [u8]) -> Result<Self, DecodingError> {
+        Self::from_slice_with_options(data, DecodeOptions::default())
+    }
+
+    pub fn from_slice_with_options(
+        data: &'a [u8],
+        options: DecodeOptions,
+    ) -> Result<Self, DecodingError> {
+        let mut decoder = Self::with_options(data, options)?;
+        decoder.slice_input = Some(SliceInput {
+            remaining: |input| input,
+            advance: |input, n| *input = &input[n..],
+        });
+        Ok(decoder)
+    }
+
+    /// Returns the compressed data of the current frame, borrowed from
+    /// the input
+    ///
+    /// It must be called right after getting `Block::ImageDescriptor`.
+    /// The decoder moves past the data, the next call to `next_record`
+    /// returns the following block.
+    pub fn image_data(
+        &mut self,
+    ) -> Result<ImageData<'a>, DecodingError> {
+        if self.state != State::ImageData {
+            return Err(ErrorKind::NoImageData.into());
+        }
+
+        let mut min_code_size_buf = [0u8; 1];
+        self.reader.read_exact(&mut min_code_size_buf)?;
+        self.state = State::Blocks;
+
+        let data: &'a [u8] = self.reader.get_ref();
+        let mut blocks = SubBlocks::new(data);
+        // A truncated sub-block is reported by the returned blocks
+        let _ = blocks.consume_to_end();
+        let len = blocks.position();
+        self.reader
+            .consume_with(len, |input, n| *input = &input[n..]);
+
+        Ok(ImageData {
+            min_code_size: min_code_size_buf[0],
+            blocks: SubBlocks::new(&data[..len]),
+        })
+    }
+}
+
+#[cfg(feature = "std")]
+impl<R: Read + Seek> Decoder<R> {
+    /// Scans the whole stream, skipping the compressed data, and records
+    /// where every frame starts
+    ///
+    /// The decoder is moved back to its current position afterwards.
+    pub fn build_frame_index(&mut self) -> Result<FrameIndex, DecodingError> {
+        let position = self.reader.position();
+        let state = self.state;
+        self.reader.seek_to(self.blocks_offset())?;
+
+        let frames = self.scan_frames();
+
+        self.reader.seek_to(position)?;
+        self.state = state;
+
+        Ok(FrameIndex::new(self.screen_descriptor, frames?))
+    }
+
+    /// Moves the decoder so that the next call to `next_frame` returns
+    /// frame `n` of `index`
+    ///
+    /// The index only holds images: with `set_render_plain_text`,
+    /// frame `n` is the `n`th image, and the text frames read before
+    /// it are not returned. Comments read again are not duplicated.
+    pub fn seek_to_frame(
+        &mut self,
+        index: &FrameIndex,
+        n: usize,
+    ) -> Result<(), DecodingError> {
+        let entry = index.get(n).ok_or_else(|| {
+            DecodingError::from(ErrorKind::FrameOutOfRange {
+                frame: n,
+                len: index.len(),
+            })
+        })?;
+
+        self.reader.seek_to(entry.start_offset())?;
+        self.state = State::Blocks;
+        self.image_count = n;
+        self.pending.clear();
+
+        Ok(())
+    }
+
+    /// Offset of the first block following the header and the Global
+    /// Color Table
+    fn blocks_offset(&self) -> u64 {
+        let palette_len = self.global_palette.as_ref().map_or(0, |p| p.len());
+        13 + 3 * palette_len as u64
+    }
+
+    fn scan_frames(&mut self) -> Result<Vec<FrameIndexEntry>, DecodingError> {
+        let mut frames = Vec::new();
+        let mut control = None;
+
+        loop {
+            let offset = self.reader.position();
+
+            let mut introducer = [0u8; 1];
+            if self.reader.read(&mut introducer)? == 0 {
+                // Missing trailer
+                return Ok(frames);
+            }
+
+            match introducer[0] {
+                0x2C => {
+                    let descriptor = self.read_image_descriptor()?;
+
+                    let palette = if descriptor.has_local_palette() {
+                        let palette_offset = self.reader.position();
+                        let size = descriptor.local_palette_size();
+                        self.read_local_palette(size)?;
+                        PaletteSource::Local(palette_offset)
+                    } else {
+                        PaletteSource::Global
+                    };
+
+                    self.skip_image_data()?;
+
+                    let (control_offset, control) = match control.take() {
+                        Some((o, c)) => (Some(o), Some(c)),
+                        None => (None, None),
+                    };
+
+                    frames.push(FrameIndexEntry {
+                        offset,
+                        control_offset,
+                        control,
+                        descriptor,
+                        palette,
+                    });
+                }
+                0x21 => {
+                    let mut label = [0u8; 1];
+                    self.reader.read_exact(&mut label)?;
+
+                    match label[0] {
+                        0xF9 => {
+                            let ext = self.read_graphic_control_ext()?;
+                            control = Some((offset, ext));
+                        }
+                        // A Graphic Control Extension followed by a
+                        // Plain Text Extension applies to the text
+                        0x01 => {
+                            control = None;
+                            self.skip_extension_blocks()?;
+                        }
+                        _ => self.skip_extension_blocks()?,
+                    }
+                }
+                0x3B => return Ok(frames),
+                0x00 => continue,
+                _ => {
+                    return Err(
+                        ErrorKind::UnknownBlock(introducer[0]).into()
+                    );
+                }
+            }
+        }
+    }
+}
+
+/// Receives the rows of a frame, see `Decoder::decode_frame_rows`
+pub trait RowSink {
+    /// Called with the color indices of the destination row `y`
+    fn row(&mut self, y: usize, indices: &[u8]);
+}
+
+impl<F: FnMut(usize, &[u8])> RowSink for F {
+    fn row(&mut self, y: usize, indices: &[u8]) {
+        self(y, indices)
+    }
+}
+
+/// Destination of the rows decoded by `Decoder::decode_rows`
+pub(crate) trait RowTarget {
+    /// Returns the buffer receiving the indices of row `y`, at least
+    /// as wide as the frame
+    fn row_buffer(&mut self, y: usize) -> &mut [u8];
+
+    /// Called once the first `len` indices of row `y` are decoded
+    fn row_decoded(&mut self, y: usize, len: usize);
+}
+
+/// Reads the signature and the Logical Screen Descriptor
+pub(crate) fn read_header(
+    reader: &mut impl Read,
+) -> Result<(Version, LogicalScreenDescriptor), DecodingError> {
+    let mut signature = [0u8; 6];
+    reader.read_exact(&mut signature)?;
+
+    let version = match &signature {
+        b"GIF89a" => Version::Gif89a,
+        b"GIF87a" => Version::Gif87a,
+        _ => return Err(ErrorKind::InvalidSignature.into()),
+    };
+
+    let mut lsd_buf = [0u8; 7];
+    reader.read_exact(&mut lsd_buf)?;
+
+    let screen_descriptor = LogicalScreenDescriptor {
+        width: u16::from_le_bytes([lsd_buf[0], lsd_buf[1]]),
+        height: u16::from_le_bytes([lsd_buf[2], lsd_buf[3]]),
+        packed_fields: lsd_buf[4],
+        bg_color_index: lsd_buf[5],
+        pixel_aspect_ration: lsd_buf[6],
+    };
+
+    Ok((version, screen_descriptor))
+}
+
+pub(crate) fn read_image_descriptor(
+    reader: &mut impl Read,
+) -> Result<ImageDescriptor, DecodingError> {
+    let mut buf = [0u8; 9];
+    reader.read_exact(&mut buf)?;
+
+    Ok(ImageDescriptor {
+        left: u16::from_le_bytes([buf[0], buf[1]]),
+        top: u16::from_le_bytes([buf[2], buf[3]]),
+        width: u16::from_le_bytes([buf[4], buf[5]]),
+        height: u16::from_le_bytes([buf[6], buf[7]]),
+        packed: buf[8],
+    })
+}
+
+/// Reads a Graphic Control Extension, after its label
+pub(crate) fn read_graphic_control_ext(
+    reader: &mut impl Read,
+    mode: DecodeMode,
+) -> Result<GraphicControl, DecodingError> {
+    // [Block Size = 4] [Packed] [Delay L] [Delay H] [Trans Index] [Terminator = 0]
+    let mut buf = [0u8; 6];
+    reader.read_exact(&mut buf)?;
+
+    if buf[0] != 4 {
+        return Err(ErrorKind::InvalidGceSize.into());
+    }
+
+    let packed = buf[1];
+    let disposal = (packed & 0b0001_1100) >> 2;
+    let has_transparency = (packed & 1) != 0;
+
+    if mode == DecodeMode::Strict {
+        if packed & 0b1110_0000 != 0 {
+            return Err(ErrorKind::ReservedBits.into());
+        }
+        if disposal > 3 {
+            return Err(ErrorKind::ReservedDisposal(disposal).into());
+        }
+        if buf[5] != 0 {
+            return Err(ErrorKind::MissingGceTerminator.into());
+        }
+    }
+
+    let delay = u16::from_le_bytes([buf[2], buf[3]]);
+    let trans_index =
+        if has_transparency { Some(buf[4]) } else { None };
+
+    Ok(GraphicControl {
+        disposal_method: DisposalMethod::from(disposal),
+        user_input_flag: (packed & 0b0000_0010) != 0,
+        delay_time_cs: delay,
+        transparent_color_index: trans_index,
+    })
+}
+
+/// Minimum code sizes above 11 would make codes exceed the 12 bits
+/// limit, the spec allows 2 to 8
+pub(crate) fn check_min_code_size(
+    min_code_size: u8,
+    mode: DecodeMode,
+) -> Result<(), DecodingError> {
+    let valid = if mode == DecodeMode::Strict {
+        (2..=8).contains(&min_code_size)
+    } else {
+        min_code_size <= 11
+    };
+
+    if valid {
+        Ok(())
+    } else {
+        Err(ErrorKind::InvalidMinCodeSize(min_code_size).into())
+    }
+}
+
+/// Checks of the strict mode on an image descriptor
+pub(crate) fn check_image_descriptor(
+    descriptor: &ImageDescriptor,
+    screen: &LogicalScreenDescriptor,
+) -> Result<(), DecodingError> {
+    if !screen_contains(descriptor, screen) {
+        return Err(ErrorKind::FrameOutsideScreen.into());
+    }
+
+    if descriptor.packed & 0b0001_1000 != 0 {
+        return Err(ErrorKind::ReservedBits.into());
+    }
+
+    Ok(())
+}
+
+/// Returns true if the frame of `descriptor` is inside the screen
+fn screen_contains(
+    descriptor: &ImageDescriptor,
+    screen: &LogicalScreenDescriptor,
+) -> bool {
+    descriptor.left as u32 + descriptor.width as u32
+        <= screen.width as u32
+        && descriptor.top as u32 + descriptor.height as u32
+            <= screen.height as u32
+}
+
+/// Decompresses the image data read by `lzw` into `target`, see
+/// `Decoder::decode_indices`
+///
+/// Returns how many pixels have been decoded and whether data is left
+/// after the last pixel.
+///
+/// The data following the End Of Information code is left unread.
+pub(crate) fn decode_image_data<D: BufRead, T: BorrowMut<LzwTables>>(
+    mut lzw: LzwDecoder<D, T>,
+    descriptor: &ImageDescriptor,
+    mode: DecodeMode,
+    target: &mut impl RowTarget,
+) -> Result<(usize, bool), DecodingError> {
+    let width = descriptor.width as usize;
+    let height = descriptor.height as usize;
+    let expected_pixels = width * height;
+
+    let mut decoded = 0;
+    let result = decode_row_order(
+        &mut lzw,
+        width,
+        height,
+        descriptor.is_interlaced(),
+        target,
+        &mut decoded,
+    );
+
+    finish_image_data(
+        &mut lzw,
+        result,
+        decoded,
+        expected_pixels,
+        mode,
+    )
+}
+
+/// Applies the rules of `mode` once the decompression of the pixels
+/// stopped with `result`, see `decode_image_data`
+pub(crate) fn finish_image_data<
+    D: BufRead,
+    T: BorrowMut<LzwTables>,
+>(
+    lzw: &mut LzwDecoder<D, T>,
+    result: io::Result<()>,
+    decoded: usize,
+    expected_pixels: usize,
+    mode: DecodeMode,
+) -> Result<(usize, bool), DecodingError> {
+    // Outside of strict mode, image data that stops early only ends
+    // the frame: invalid codes are the only errors of standard mode
+    match result {
+        Ok(()) => {}
+        Err(e)
+            if mode == DecodeMode::Strict
+                || (mode == DecodeMode::Standard
+                    && e.kind() == io::ErrorKind::InvalidData) =>
+        {
+            return Err(DecodingError::image_data(e));
+        }
+        Err(_) => return Ok((decoded, false)),
+    }
+
+    if decoded < expected_pixels {
+        if mode == DecodeMode::Strict {
+            return Err(ErrorKind::MissingPixels {
+                decoded,
+                expected: expected_pixels,
+            }
+            .into());
+        }
+        return Ok((decoded, false));
+    }
+
+    // Anything but the End Of Information code is too much
+    if mode == DecodeMode::Strict {
+        match lzw.decode_bytes(&mut [0u8; 1]) {
+            Ok(0) if lzw.is_finished() => {}
+            Ok(0) => return Err(ErrorKind::MissingEndCode.into()),
+            Ok(_) => return Err(ErrorKind::ExcessPixels.into()),
+            // The data stops before the code
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => {
+                return Err(ErrorKind::MissingEndCode.into());
+            }
+            Err(e) => return Err(DecodingError::image_data(e)),
+        }
+    }
+
+    Ok((decoded, lzw.has_excess_data()))
+}
+
+fn decode_row_order<D: BufRead, T: BorrowMut<LzwTables>>(
+    lzw: &mut LzwDecoder<D, T>,
+    width: usize,
+    height: usize,
+    interlaced: bool,
+    target: &mut impl RowTarget,
+    decoded: &mut usize,
+) -> io::Result<()> {
+    let mut rows = RowOrder::new(height, interlaced);
+    let mut row = if width > 0 { rows.next() } else { None };
+    let mut x = 0;
+
+    while let Some(y) = row {
+        let n = match lzw.decode_bytes(&mut target.row_buffer(y)[x..width]) {
+            Ok(n) => n,
+            Err(e) => {
+                if x > 0 {
+                    target.row_decoded(y, x);
+                }
+                return Err(e);
+            }
+        };
+        if n == 0 {
+            if x > 0 {
+                target.row_decoded(y, x);
+            }
+            break;
+        }
+
+        x += n;
+        *decoded += n;
+        if x == width {
+            target.row_decoded(y, width);
+            x = 0;
+            row = rows.next();
+        }
+    }
+
+    Ok(())
+}
+
+/// Rows decoded in place in a frame sized index buffer
+pub(crate) struct IndexRows<'a> {
+    pub buffer: &'a mut [u8],
+    pub width: usize,
+}
+
+impl RowTarget for IndexRows<'_> {
+    fn row_buffer(&mut self, y: usize) -> &mut [u8] {
+        &mut self.buffer[y * self.width..]
+    }
+
+    fn row_decoded(&mut self, _y: usize, _len: usize) {}
+}
+
+/// Rows decoded in a single row of indices and passed to a `RowSink`
+struct SinkRows<'a, S: RowSink> {
+    row: &'a mut [u8],
+    sink: &'a mut S,
+}
+
+impl<S: RowSink> RowTarget for SinkRows<'_, S> {
+    fn row_buffer(&mut self, _y: usize) -> &mut [u8] {
+        self.row
+    }
+
+    fn row_decoded(&mut self, y: usize, len: usize) {
+        self.sink.row(y, &self.row[..len]);
+    }
+}
+
+/// Rows decoded in a single row of indices and copied to an index
+/// buffer if they are in the crop
+pub(crate) struct CropRows<'a> {
+    pub row: &'a mut [u8],
+    pub buffer: &'a mut [u8],
+    pub crop: Region,
+}
+
+impl RowTarget for CropRows<'_> {
+    fn row_buffer(&mut self, _y: usize) -> &mut [u8] {
+        self.row
+    }
+
+    fn row_decoded(&mut self, y: usize, len: usize) {
+        if let Some(indices) = crop_row(&self.row[..len], y, &self.crop) {
+            let width = self.crop.width as usize;
+            let start = (y - self.crop.top as usize) * width;
+            self.buffer[start..start + indices.len()]
+                .copy_from_slice(indices);
+        }
+    }
+}
+
+/// Returns the part of the row `y` of a frame inside `crop`, or `None`
+/// if the row is outside of it
+fn crop_row<'a>(row: &'a [u8], y: usize, crop: &Region) -> Option<&'a [u8]> {
+    let top = crop.top as usize;
+    if y < top || y >= top + crop.height as usize {
+        return None;
+    }
+
+    let left = (crop.left as usize).min(row.len());
+    let right = (left + crop.width as usize).min(row.len());
+    Some(&row[left..right])
+}
+
+/// Returns true if `crop` covers the whole frame
+pub(crate) fn crop_is_whole(crop: &Region, descriptor: &ImageDescriptor) -> bool {
+    crop.width == descriptor.width && crop.height == descriptor.height
+}
+
+/// Returns the descriptor of the cropped part of a frame
+fn cropped_descriptor(
+    descriptor: &ImageDescriptor,
+    crop: &Region,
+) -> ImageDescriptor {
+    ImageDescriptor {
+        left: descriptor.left + crop.left,
+        top: descriptor.top + crop.top,
+        width: crop.width,
+        height: crop.height,
+        packed: descriptor.packed,
+    }
+}
+
+/// Rows decoded in a single row of indices and expanded to pixels in a
+/// caller provided buffer
+struct PixelRows<'a, P: Pixel> {
+    row: &'a mut [u8],
+    table: [P; 256],
+    palette_len: usize,
+    transparent_index: Option<u8>,
+    // False if every index is in the palette
+    check_indices: bool,
+    // First index beyond the palette, if `check_indices`
+    invalid_index: Option<u8>,
+    buffer: &'a mut [u8],
+    layout: BufferLayout,
+    // Part of the frame written to `buffer`
+    crop: Region,
+}
+
+impl<P: Pixel> RowTarget for PixelRows<'_, P> {
+    fn row_buffer(&mut self, _y: usize) -> &mut [u8] {
+        self.row
+    }
+
+    fn row_decoded(&mut self, y: usize, len: usize) {
+        let indices = &self.row[..len];
+
+        if self.check_indices && self.invalid_index.is_none() {
+            self.invalid_index = indices.iter().copied().find(|&i| {
+                i as usize >= self.palette_len
+                    && Some(i) != self.transparent_index
+            });
+        }
+
+        let Some(indices) = crop_row(indices, y, &self.crop) else {
+            return;
+        };
+
+        let top = self.crop.top as usize;
+        let out = self.layout.row::<P>(self.buffer, y - top, indices.len());
+        for (bytes, &index) in out.chunks_exact_mut(P::SIZE).zip(indices) {
+            self.table[index as usize].write_bytes(bytes);
+        }
+    }
+}
+
+fn frame_info(
+    descriptor: &ImageDescriptor,
+    control_ext: Option<GraphicControl>,
+    complete: bool,
+) -> FrameInfo {
+    FrameInfo {
+        delay_cs: control_ext.map(|x| x.delay_time_cs).unwrap_or(0),
+        disposal: control_ext
+            .map(|x| x.disposal_method)
+            .unwrap_or_default(),
+        left: descriptor.left,
+        top: descriptor.top,
+        width: descriptor.width,
+        height: descriptor.height,
+        transparent_index: control_ext
+            .and_then(|x| x.transparent_color_index),
+        complete,
+    }
+}
+
+pub(crate) fn invalid_index_error(index: u8, palette_len: usize) -> DecodingError {
+    ErrorKind::InvalidColorIndex { index, palette_len }.into()
+}
+
+/// Concatenates the content of sub-blocks read with their length bytes
+fn unframe_sub_blocks(mut raw: &[u8]) -> Vec<u8> {
+    let mut data = Vec::with_capacity(raw.len());
+    while let [len, rest @ ..] = raw {
+        let len = (*len as usize).min(rest.len());
+        data.extend_from_slice(&rest[..len]);
+        raw = &rest[len..];
+    }
+    data
+}
+
+/// The XMP magic trailer is 0x01, 0xFF, 0xFE, ..., 0x01, 0x00 followed
+/// by the block terminator: whatever byte of the ramp is read as a
+/// length, the reader lands on the terminator.
+/// Depending on where it lands, the last 0x00 of the ramp can be taken
+/// as the terminator itself.
+fn strip_xmp_trailer(mut raw: Vec<u8>) -> Vec<u8> {
+    const TRAILER_LEN: usize = 257;
+
+    let ramp = |i: usize| if i == 0 { 1 } else { (256 - i) as u8 };
+
+    for len in [TRAILER_LEN, TRAILER_LEN - 1] {
+        if raw.len() >= len {
+            let start = raw.len() - len;
+            if raw[start..].iter().enumerate().all(|(i, &b)| b == ramp(i)) {
+                raw.truncate(start);
+                break;
+            }
+        }
+    }
+
+    raw
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::string::String;
+
+    use super::*;
+    use crate::pixel::GifColor;
+    use crate::test_gif::{self, TestFrame};
+
+    #[test]
+    fn netscape_sub_blocks_are_parsed_on_their_own() {
+        let frame = TestFrame::new(0, 0, 4, 4);
+        let mut data = test_gif::gif(4, 4, &[0; 24], &[frame]);
+        // A padded loop count sub-block, then the buffering size
+        let at = data.windows(11).position(|w| w == b"NETSCAPE2.0");
+        let at = at.unwrap() + 11;
+        data.splice(
+            at..at + 5,
+            [5, 1, 7, 0, 0xEE, 0xEE, 5, 2, 0, 1, 0, 0, 0],
+        );
+
+        let mut decoder = Decoder::new(&data[..]).unwrap();
+        while decoder.next_frame().unwrap().is_some() {}
+        assert_eq!(decoder.loop_count(), Some(LoopCount::Finite(7)));
+        assert_eq!(decoder.buffer_size(), Some(256));
+    }
+
+    #[test]
+    fn frame_pixel_limit_of_caller_buffers() {
+        let frame = TestFrame::new(0, 0, 8, 8);
+        let data = test_gif::gif(8, 8, &[0; 24], &[frame]);
+        let options = DecodeOptions::new().max_frame_pixels(63);
+
+        for rows in [false, true] {
+            let mut decoder =
+                Decoder::with_options(&data[..], options.clone())
+                    .unwrap();
+            let descriptor = loop {
+                if let Block::ImageDescriptor(descriptor, _) =
+                    decoder.next_record().unwrap()
+                {
+                    break descriptor;
+                }
+            };
+
+            let mut buffer = [0u8; 64];
+            let result = match rows {
+                false => {
+                    decoder
+                        .decode_frame_into(&descriptor, &mut buffer)
+                }
+                true => decoder.decode_frame_rows(
+                    &descriptor,
+                    &mut |_: usize, _: &[u8]| {},
+                ),
+            };
+            assert_eq!(
+                result.unwrap_err().kind(),
+                ErrorKind::LimitExceeded(Limit::FramePixels)
+            );
+        }
+    }
+
+    /// A GIF whose single frame of `size` pixels square has the given
+    /// LZW data, min code size 3
+    fn with_image_data(size: u8, lzw: &[u8]) -> Vec<u8> {
+        let mut data = test_gif::gif(4, 4, &[0; 24], &[]);
+        data.pop();
+        data.extend_from_slice(&[0x2C, 0, 0, 0, 0, size, 0, size, 0]);
+        data.extend_from_slice(&[0, 3]);
+        data.extend_from_slice(&test_gif::sub_blocks(lzw, 255));
+        data.push(0x3B);
+        data
+    }
+
+    fn first_frame(
+        data: &[u8],
+        mode: DecodeMode,
+    ) -> Result<Option<Frame>, DecodingError> {
+        let options = DecodeOptions::new().mode(mode);
+        Decoder::with_options(data, options)?.next_frame()
+    }
+
+    #[test]
+    fn lzw_data_stopping_early() {
+        let indices = TestFrame::new(0, 0, 4, 4).indices;
+        let lzw = test_gif::lzw(3, &indices, None);
+        let data = with_image_data(4, &lzw[..3]);
+
+        for mode in [DecodeMode::Standard, DecodeMode::Lenient] {
+            let frame = first_frame(&data, mode).unwrap().unwrap();
+            assert!(!frame.complete);
+        }
+        assert!(first_frame(&data, DecodeMode::Strict).is_err());
+    }
+
+    #[test]
+    fn invalid_lzw_code() {
+        // Clear code, then a code beyond the dictionary
+        let data = with_image_data(4, &[0xF8]);
+
+        let frame = first_frame(&data, DecodeMode::Lenient).unwrap();
+        assert!(!frame.unwrap().complete);
+        for mode in [DecodeMode::Standard, DecodeMode::Strict] {
+            let error = first_frame(&data, mode).unwrap_err();
+            assert_eq!(error.kind(), ErrorKind::InvalidLzwCode);
+        }
+    }
+
+    #[test]
+    fn lenient_truncated_extension() {
+        let mut data = test_gif::gif(4, 4, &[0; 24], &[]);
+        data.truncate(data.len() - 3);
+
+        let options = DecodeOptions::new().mode(DecodeMode::Lenient);
+        let mut decoder =
+            Decoder::with_options(&data[..], options).unwrap();
+        assert!(decoder.next_frame().is_err());
+    }
+
+    #[test]
+    fn strict_end_of_information() {
+        // 4 bit codes: clear (8), index 0, then the 4 bits of `next`
+        let strict = |next: Option<u8>| {
+            let lzw: &[u8] = match next {
+                Some(code) => &[0x08, code],
+                None => &[0x08],
+            };
+            let data = with_image_data(1, lzw);
+            first_frame(&data, DecodeMode::Strict).map(|_| ())
+        };
+
+        assert!(strict(Some(9)).is_ok());
+        let kind = |next| strict(next).unwrap_err().kind();
+        assert_eq!(kind(Some(1)), ErrorKind::ExcessPixels);
+        assert_eq!(kind(Some(15)), ErrorKind::InvalidLzwCode);
+        assert_eq!(kind(None), ErrorKind::MissingEndCode);
+    }
+
+    /// The pixels of a frame and whether it is complete, or an error
+    /// and its offset, with the warnings found meanwhile
+    #[cfg(feature = "std")]
+    type Outcome = (
+        Result<(Vec<GifColor>, bool), (ErrorKind, Option<u64>)>,
+        Vec<Warning>,
+    );
+
+    #[cfg(feature = "std")]
+    fn decode_all(
+        data: &[u8],
+        mode: DecodeMode,
+        threads: usize,
+    ) -> Vec<Outcome> {
+        let options =
+            DecodeOptions::new().mode(mode).threads(threads);
+        outcomes(Decoder::with_options(data, options))
+    }
+
+    #[cfg(feature = "std")]
+    fn outcomes(
+        decoder: Result<Decoder<&[u8]>, DecodingError>,
+    ) -> Vec<Outcome> {
+        let mut decoder = match decoder {
+            Ok(decoder) => decoder,
+            Err(e) => {
+                let error = (e.kind(), e.offset());
+                return vec![(Err(error), Vec::new())];
+            }
+        };
+
+        let mut frames = Vec::new();
+        loop {
+            let result = match decoder.next_frame() {
+                Ok(Some(frame)) => Ok((frame.pixels, frame.complete)),
+                Ok(None) => break,
+                Err(e) => Err((e.kind(), e.offset())),
+            };
+            let end = result.is_err();
+            frames.push((result, decoder.take_warnings()));
+            if end {
+                break;
+            }
+        }
+        frames
+    }
+
+    /// The test animation, truncated or with corrupt bytes
+    #[cfg(feature = "std")]
+    fn damaged_inputs() -> Vec<Vec<u8>> {
+        let data = test_gif::animation();
+        let mut inputs = vec![data.clone()];
+        // Truncated inside sub-blocks and before terminators
+        let truncated = (0..data.len()).step_by(5);
+        inputs.extend(truncated.map(|n| data[..n].to_vec()));
+        // Corrupt image data, lengths and terminators
+        for at in (40..data.len()).step_by(3) {
+            for value in [0, 0xFF] {
+                let mut corrupt = data.clone();
+                corrupt[at] = value;
+                inputs.push(corrupt);
+            }
+        }
+        inputs
+    }
+
+    #[cfg(feature = "std")]
+    const MODES: [DecodeMode; 3] = [
+        DecodeMode::Standard,
+        DecodeMode::Lenient,
+        DecodeMode::Strict,
+    ];
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn same_results_on_threads() {
+        let inputs = damaged_inputs();
+        for mode in MODES {
+            for input in &inputs {
+                assert_eq!(
+                    decode_all(input, mode, 1),
+                    decode_all(input, mode, 4),
+                    "{:?} mode, {} bytes",
+                    mode,
+                    input.len()
+                );
+            }
+        }
+    }
+
+    /// Batches of fewer frames than threads, down to a single frame
+    #[cfg(feature = "std")]
+    #[test]
+    fn few_frames_on_threads() {
+        let mut interlaced = TestFrame::new(1, 2, 5, 9);
+        interlaced.interlaced = true;
+        let small = TestFrame::new(2, 2, 3, 3);
+        let frames = [TestFrame::new(0, 0, 8, 12), interlaced, small];
+
+        for count in 1..=frames.len() {
+            let frames = &frames[..count];
+            let data = test_gif::gif(8, 12, &[7; 24], frames);
+            let mut inputs = vec![data.clone()];
+            let truncated = (30..data.len()).map(|n| &data[..n]);
+            inputs.extend(truncated.map(<[u8]>::to_vec));
+
+            for input in &inputs {
+                let mode = DecodeMode::Standard;
+                let expected = decode_all(input, mode, 1);
+                for threads in [2, 3, 8] {
+                    assert_eq!(
+                        decode_all(input, mode, threads),
+                        expected,
+                        "{} frames on {} threads, {} bytes",
+                        count,
+                        threads,
+                        input.len()
+                    );
+                }
+            }
+        }
+    }
+
+    /// The threads are started once and decode every batch
+    #[cfg(feature = "std")]
+    #[test]
+    fn threads_kept_for_the_stream() {
+        let frames: Vec<_> = (0..30)
+            .map(|i| TestFrame::new(i % 5, i % 3, 6, 4))
+            .collect();
+        let data = test_gif::gif(12, 8, &[7; 24], &frames);
+
+        let single = test_gif::gif(12, 8, &[7; 24], &frames[..1]);
+        let options = DecodeOptions::new().threads(3);
+        let mut decoder =
+            Decoder::with_options(&single[..], options.clone())
+                .unwrap();
+        decoder.next_frame().unwrap().unwrap();
+        assert!(decoder.workers.thread_ids().is_empty());
+
+        let mut decoder =
+            Decoder::with_options(&data[..], options).unwrap();
+        decoder.next_frame().unwrap().unwrap();
+        let threads = decoder.workers.thread_ids();
+        assert_eq!(threads.len(), 3);
+
+        let mut count = 1;
+        while decoder.next_frame().unwrap().is_some() {
+            count += 1;
+        }
+        assert_eq!(count, frames.len());
+        assert_eq!(decoder.workers.thread_ids(), threads);
+    }
+
+    /// Decoding a slice in place gives the same results as reading it
+    #[cfg(feature = "std")]
+    #[test]
+    fn same_results_from_a_slice() {
+        let inputs = damaged_inputs();
+        for mode in MODES {
+            for input in &inputs {
+                let options = DecodeOptions::new().mode(mode);
+                let slice = Decoder::from_slice_with_options(
+                    input,
+                    options.clone(),
+                );
+                let reader =
+                    Decoder::with_options(&input[..], options);
+                assert_eq!(
+                    outcomes(slice),
+                    outcomes(reader),
+                    "{:?} mode, {} bytes",
+                    mode,
+                    input.len()
+                );
+            }
+        }
+    }
+
+    /// Cuts `window`, in screen coordinates, out of the values of a
+    /// frame at `frame`
+    fn cut<T: Copy>(
+        values: &[T],
+        frame: Region,
+        window: Region,
+    ) -> Vec<T> {
+        let width = frame.width as usize;
+        let left = (window.left - frame.left) as usize;
+        let top = (window.top - frame.top) as usize;
+        (top..top + window.height as usize)
+            .flat_map(|y| {
+                &values[y * width + left..][..window.width as usize]
+            })
+            .copied()
+            .collect()
+    }
+
+    fn rect(frame: &FrameInfo) -> Region {
+        Region::new(frame.left, frame.top, frame.width, frame.height)
+    }
+
+    #[test]
+    fn region_crops_frames() {
+        let data = test_gif::animation();
+        let mut full = Decoder::new(&data[..]).unwrap();
+        let mut frames = Vec::new();
+        while let Some(frame) = full.next_indexed_frame().unwrap() {
+            let pixels = pixel::lookup_table::<GifColor>(
+                &frame.palette,
+                frame.transparent_index,
+            );
+            let pixels: Vec<_> = frame
+                .indices
+                .iter()
+                .map(|&i| pixels[i as usize])
+                .collect();
+            frames.push((frame.info(), frame.indices, pixels));
+        }
+
+        // The second frame is outside of the last two regions
+        let regions = [
+            Region::new(4, 3, 20, 12),
+            Region::new(20, 15, 20, 15),
+            Region::new(39, 0, 1, 30),
+            Region::new(0, 0, 40, 30),
+        ];
+        for region in regions {
+            let options = DecodeOptions::new().region(region);
+            let mut rgba =
+                Decoder::with_options(&data[..], options.clone())
+                    .unwrap();
+            let mut indexed =
+                Decoder::with_options(&data[..], options.clone())
+                    .unwrap();
+            let mut into =
+                Decoder::with_options(&data[..], options).unwrap();
+
+            for (info, indices, pixels) in &frames {
+                let visible = rect(info).intersect(&region);
+
+                let frame = rgba.next_frame().unwrap().unwrap();
+                let frame_rect = Region::new(
+                    frame.left,
+                    frame.top,
+                    frame.width,
+                    frame.height,
+                );
+                assert_eq!(frame_rect, visible);
+                assert_eq!(
+                    frame.pixels,
+                    cut(pixels, rect(info), visible)
+                );
+
+                let frame =
+                    indexed.next_indexed_frame().unwrap().unwrap();
+                assert_eq!(rect(&frame.info()), visible);
+                assert_eq!(
+                    frame.indices,
+                    cut(indices, rect(info), visible)
+                );
+
+                let mut buffer = [0u8; 40 * 30 * 4];
+                let layout = BufferLayout::new(0, 40 * 4);
+                let frame = into
+                    .next_frame_into::<GifColor>(&mut buffer, layout)
+                    .unwrap()
+                    .unwrap();
+                assert_eq!(rect(&frame), visible);
+                let width = visible.width as usize;
+                let expected = cut(pixels, rect(info), visible);
+                for (y, row) in
+                    expected.chunks(width.max(1)).enumerate()
+                {
+                    let out = &buffer[y * 160..][..width * 4];
+                    let bytes: Vec<u8> = row
+                        .iter()
+                        .flat_map(|c| [c.r, c.g, c.b, c.a])
+                        .collect();
+                    assert_eq!(out, bytes);
+                }
+            }
+            assert!(rgba.next_frame().unwrap().is_none());
+        }
+    }
+
+    /// Comments are kept with the index of the frame that follows
+    /// them, their text decoded as UTF-8 or else as Latin-1
+    #[test]
+    fn comments_with_their_position() {
+        let mut data = test_gif::gif(4, 4, &[0; 6], &[]);
+        data.pop();
+        test_gif::comment(&mut data, &[b"made by ", b"hand"]);
+        TestFrame::new(0, 0, 4, 4).write(&mut data);
+        test_gif::comment(&mut data, &[b"caf\xE9"]);
+        TestFrame::new(0, 0, 4, 4).write(&mut data);
+        test_gif::comment(&mut data, &["d\u{e9}j\u{e0}".as_bytes()]);
+        data.push(0x3B);
+
+        let mut decoder = Decoder::new(&data[..]).unwrap();
+        assert!(decoder.comments().is_empty());
+        while decoder.next_frame().unwrap().is_some() {}
+
+        let comments: Vec<_> = decoder
+            .comments()
+            .iter()
+            .map(|c| (c.frame_index, c.text()))
+            .collect();
+        let expected = [
+            (0, "made by hand".into()),
+            (1, "caf\u{e9}".into()),
+            (2, "d\u{e9}j\u{e0}".into()),
+        ];
+        assert_eq!(comments, expected);
+        assert_eq!(decoder.comments()[1].bytes(), b"caf\xE9");
+    }
+
+    /// Plain Text frames are only returned when enabled, drawn with
+    /// the built-in font and the preceding Graphic Control Extension
+    #[test]
+    fn plain_text_frames() {
+        // Two cells of 6x8 pixels: the last character is dropped
+        let text = PlainText {
+            left: 2,
+            top: 1,
+            width: 12,
+            height: 8,
+            cell_width: 6,
+            cell_height: 8,
+            fg_color_index: 1,
+            bg_color_index: 2,
+            text: b"|-?".to_vec(),
+        };
+        let image = TestFrame::new(0, 0, 16, 10);
+        let mut data = test_gif::gif(16, 10, &[0; 24], &[image]);
+        data.pop();
+        data.extend_from_slice(&[0x21, 0xF9, 4, 1, 7, 0, 3, 0]);
+        test_gif::plain_text(&mut data, &text);
+        TestFrame::new(0, 0, 2, 2).write(&mut data);
+        data.push(0x3B);
+
+        let mut decoder = Decoder::new(&data[..]).unwrap();
+        let mut count = 0;
+        while decoder.next_indexed_frame().unwrap().is_some() {
+            count += 1;
+        }
+        assert_eq!(count, 2);
+
+        let mut decoder = Decoder::new(&data[..]).unwrap();
+        decoder.set_render_plain_text(true);
+        decoder.next_indexed_frame().unwrap().unwrap();
+        let frame = decoder.next_indexed_frame().unwrap().unwrap();
+        let rect = (frame.left, frame.top, frame.width, frame.height);
+        assert_eq!(rect, (2, 1, 12, 8));
+        assert_eq!(frame.delay_cs, 7);
+        assert_eq!(frame.transparent_index, Some(3));
+
+        let rows: Vec<String> = frame
+            .indices
+            .chunks(12)
+            .map(|row| {
+                row.iter()
+                    .map(|&i| match i {
+                        1 => '#',
+                        2 => '.',
+                        _ => '?',
+                    })
+                    .collect()
+            })
+            .collect();
+        let expected = [
+            "..#.........",
+            "..#.........",
+            "..#.........",
+            "..#...#####.",
+            "..#.........",
+            "..#.........",
+            "..#.........",
+            "............",
+        ];
+        assert_eq!(rows, expected);
+        let last = decoder.next_indexed_frame().unwrap().unwrap();
+        assert_eq!(last.width, 2);
+    }
+
+    /// The XMP packet is returned without the magic trailer, whatever
+    /// byte of it ends the sub-blocks
+    #[test]
+    fn xmp_packet_without_trailer() {
+        for len in 0..300 {
+            let packet: Vec<u8> = b"<x:xmpmeta>"
+                .iter()
+                .chain(b"abc".iter().cycle().take(len))
+                .chain(b"</x:xmpmeta>")
+                .copied()
+                .collect();
+
+            let frame = TestFrame::new(0, 0, 4, 4);
+            let mut data = test_gif::gif(4, 4, &[0; 24], &[frame]);
+            // After the Global Color Table
+            let at = 13 + 24;
+            let mut xmp = [0x21, 0xFF, 11].to_vec();
+            xmp.extend_from_slice(b"XMP DataXMP");
+            xmp.extend_from_slice(&packet);
+            xmp.push(1);
+            xmp.extend((0..=255).rev());
+            xmp.push(0);
+            data.splice(at..at, xmp);
+
+            let mut decoder = Decoder::new(&data[..]).unwrap();
+            assert!(decoder.next_frame().unwrap().is_some());
+            assert_eq!(decoder.xmp_packet(), Some(&packet[..]));
+            assert!(decoder.next_frame().unwrap().is_none());
+        }
+    }
+
+    /// Indexed frames hold their indices in display order, with the
+    /// palette they refer to: the Global palette is shared
+    #[test]
+    fn indexed_frames() {
+        let palette: Vec<u8> = (0..24).collect();
+        let mut interlaced = TestFrame::new(1, 2, 5, 9);
+        interlaced.interlaced = true;
+        interlaced.transparent = Some(4);
+        let mut local = TestFrame::new(0, 0, 3, 3);
+        local.local_palette = Some((100..112).collect());
+        local.indices.iter_mut().for_each(|i| *i %= 4);
+        local.min_code_size = 2;
+        let frames = [TestFrame::new(0, 0, 8, 8), interlaced, local];
+        let data = test_gif::gif(8, 12, &palette, &frames);
+
+        let mut decoder = Decoder::new(&data[..]).unwrap();
+        let global = decoder.global_palette.clone().unwrap();
+        for expected in &frames {
+            let frame =
+                decoder.next_indexed_frame().unwrap().unwrap();
+            assert_eq!(frame.indices, expected.indices);
+            assert_eq!(frame.transparent_index, expected.transparent);
+            match &expected.local_palette {
+                None => assert!(Arc::ptr_eq(&frame.palette, &global)),
+                Some(local) => {
+                    let colors: Vec<u8> = frame
+                        .palette
+                        .iter()
+                        .flat_map(|c| [c.r, c.g, c.b])
+                        .collect();
+                    assert_eq!(&colors, local);
+                }
+            }
+        }
+        assert!(decoder.next_indexed_frame().unwrap().is_none());
+    }
+
+    /// Interlaced rows are passed in file order, with their
+    /// destination row
+    #[test]
+    fn rows_of_an_interlaced_frame() {
+        let mut frame = TestFrame::new(0, 0, 3, 10);
+        frame.interlaced = true;
+        let indices = frame.indices.clone();
+        let data = test_gif::gif(3, 10, &[0; 24], &[frame]);
+
+        let mut decoder = Decoder::new(&data[..]).unwrap();
+        let descriptor = loop {
+            if let Block::ImageDescriptor(descriptor, _) =
+                decoder.next_record().unwrap()
+            {
+                break descriptor;
+            }
+        };
+
+        let mut rows = Vec::new();
+        let mut sink =
+            |y: usize, row: &[u8]| rows.push((y, row.to_vec()));
+        let complete = decoder.decode_frame_rows(&descriptor, &mut sink);
+        assert!(complete.unwrap());
+
+        let order: Vec<_> = rows.iter().map(|(y, _)| *y).collect();
+        assert_eq!(order, [0, 8, 4, 2, 6, 1, 3, 5, 7, 9]);
+        for (y, row) in &rows {
+            assert_eq!(row[..], indices[y * 3..(y + 1) * 3]);
+        }
+    }
+
+    /// Errors are located at the start of the block being read, in
+    /// the last frame whose Image Descriptor was found
+    #[test]
+    fn error_locations() {
+        let frames =
+            [TestFrame::new(0, 0, 4, 4), TestFrame::new(0, 0, 4, 4)];
+        let data = test_gif::gif(4, 4, &[0; 24], &frames);
+        let mut frame_len = Vec::new();
+        frames[0].write(&mut frame_len);
+        // After the header, the palette and the NETSCAPE2.0 extension
+        let second_control = (13 + 24 + 19 + frame_len.len()) as u64;
+        let second_data = second_control + 8 + 10;
+
+        let location = |data: &[u8]| {
+            let error = match Decoder::new(data) {
+                Ok(mut decoder) => loop {
+                    if let Err(e) = decoder.next_frame() {
+                        break e;
+                    }
+                },
+                Err(e) => e,
+            };
+            (error.kind(), error.offset(), error.frame())
+        };
+
+        let mut signature = data.clone();
+        signature[3] = b'9';
+        assert_eq!(
+            location(&signature),
+            (ErrorKind::InvalidSignature, Some(0), None)
+        );
+
+        let mut control = data.clone();
+        control[second_control as usize + 2] = 5;
+        assert_eq!(
+            location(&control),
+            (ErrorKind::InvalidGceSize, Some(second_control), Some(0))
+        );
+
+        let mut image_data = data.clone();
+        let codes = second_data as usize + 2;
+        image_data[codes..codes + 2].fill(0xFF);
+        assert_eq!(
+            location(&image_data),
+            (ErrorKind::InvalidLzwCode, Some(second_data), Some(1))
+        );
+
+        let mut decoder = Decoder::new(&data[..]).unwrap();
+        let layout = BufferLayout::packed::<GifColor>(4);
+        let error = decoder
+            .next_frame_into::<GifColor>(&mut [0; 63], layout)
+            .unwrap_err();
+        assert_eq!((error.offset(), error.frame()), (None, None));
+    }
+
+    /// Each defect is reported with the frame it was found in
+    #[test]
+    fn warning_kinds() {
+        let mut outside = TestFrame::new(2, 2, 4, 4);
+        outside.indices.iter_mut().for_each(|i| *i %= 4);
+        let invalid = TestFrame::new(0, 0, 4, 4);
+        let mut excess = TestFrame::new(0, 0, 2, 2);
+        excess.indices = vec![1; 8];
+        let frames = [outside, invalid, excess];
+        let mut data = test_gif::gif(4, 4, &[0; 12], &frames);
+        data.pop();
+
+        let options = DecodeOptions::new().mode(DecodeMode::Lenient);
+        let mut decoder =
+            Decoder::with_options(&data[..], options).unwrap();
+        while decoder.next_frame().unwrap().is_some() {}
+
+        let warnings: Vec<_> = decoder
+            .take_warnings()
+            .iter()
+            .map(|w| (w.kind(), w.frame()))
+            .collect();
+        let invalid_index = WarningKind::InvalidColorIndex {
+            index: 4,
+            palette_len: 4,
+        };
+        let expected = [
+            (WarningKind::FrameOutsideScreen, Some(0)),
+            (invalid_index, Some(1)),
+            (WarningKind::ExcessImageData, Some(2)),
+            (WarningKind::MissingTrailer, Some(2)),
+        ];
+        assert_eq!(warnings, expected);
+        assert!(decoder.warnings().is_empty());
+    }
+
+    /// The comments and ICC profiles are kept without a copy, their
+    /// bytes count once towards the limit
+    #[test]
+    fn extension_data_is_charged_once() {
+        let mut data = test_gif::gif(1, 1, &[0; 6], &[]);
+        data.pop();
+        data.extend_from_slice(&[0x21, 0xFE, 5]);
+        data.extend_from_slice(b"hello");
+        data.extend_from_slice(&[0, 0x21, 0xFF, 11]);
+        data.extend_from_slice(b"ICCRGBG1012");
+        data.extend_from_slice(&[3, 1, 2, 3, 0, 0x3B]);
+
+        let mut decoder = Decoder::new(&data[..]).unwrap();
+        let mut blocks = Vec::new();
+        loop {
+            let before = decoder.allocated_bytes;
+            let data = match decoder.next_record().unwrap() {
+                Block::Comment(data) => data,
+                Block::Application {
+                    identifier: ICC_IDENTIFIER,
+                    data,
+                    ..
+                } => data,
+                Block::Trailer => break,
+                _ => continue,
+            };
+            blocks.push((data, decoder.allocated_bytes - before));
+        }
+
+        let expected =
+            [(b"hello".to_vec(), 5), ([1, 2, 3].to_vec(), 3)];
+        assert_eq!(blocks, expected);
+        assert_eq!(decoder.comments()[0].data, b"hello");
+        assert_eq!(decoder.icc_profile(), Some(&[1, 2, 3][..]));
+    }
+
+    /// Decoding frames into caller buffers allocates nothing once the
+    /// reused buffers have grown
+    #[cfg(feature = "std")]
+    #[test]
+    fn no_allocation_per_frame() {
+        use crate::pixel::Bgra8;
+
+        let mut frames = Vec::new();
+        for _ in 0..3 {
+            let mut interlaced = TestFrame::new(2, 1, 12, 13);
+            interlaced.interlaced = true;
+            interlaced.disposal = 3;
+
+            let mut local = TestFrame::new(0, 0, 16, 16);
+            local.local_palette = Some(
+                [255, 0, 0, 0, 255, 0, 0, 0, 255, 9, 9, 9].to_vec(),
+            );
+            local.indices.iter_mut().for_each(|i| *i %= 4);
+            local.min_code_size = 2;
+            local.transparent = Some(1);
+            local.disposal = 2;
+
+            let whole = TestFrame::new(0, 0, 16, 16);
+            frames.extend([whole, interlaced, local]);
+        }
+        let data = test_gif::gif(16, 16, &[7; 24], &frames);
+        let layout = BufferLayout::packed::<Bgra8>(16);
+        let mut buffer = [0u8; 16 * 16 * 4];
+
+        // The first 3 frames grow the buffers
+        let decoders = [
+            Decoder::new(&data[..]).unwrap(),
+            Decoder::from_slice(&data).unwrap(),
+        ];
+        for mut decoder in decoders {
+            let mut next = || {
+                decoder
+                    .next_frame_into::<Bgra8>(&mut buffer, layout)
+                    .unwrap()
+                    .is_some()
+            };
+            (0..3).for_each(|_| assert!(next()));
+            let count = test_gif::allocations(|| {
+                (3..9).for_each(|_| assert!(next()))
+            });
+            assert_eq!(count, 0);
+            assert!(!next());
+        }
+
+        let decoder = Decoder::new(&data[..]).unwrap();
+        let mut stream = decoder.into_stream_as::<Bgra8>().unwrap();
+        let mut next =
+            || stream.next_into(&mut buffer, layout).unwrap();
+        (0..3).for_each(|_| assert!(next()));
+        let count = test_gif::allocations(|| {
+            (3..9).for_each(|_| assert!(next()))
+        });
+        assert_eq!(count, 0);
+        assert!(!next());
+    }
+
+    /// Frame `n` of the index is the `n`th image, also when plain
+    /// text is rendered, and the comments read again are kept once
+    #[cfg(feature = "std")]
+    #[test]
+    fn seek_with_comments_and_plain_text() {
+        let data = test_gif::text_and_comments();
+        let mut decoder =
+            Decoder::new(std::io::Cursor::new(&data)).unwrap();
+        decoder.set_render_plain_text(true);
+        let index = decoder.build_frame_index().unwrap();
+        assert_eq!(index.len(), 3);
+
+        let mut sizes = Vec::new();
+        while let Some(f) = decoder.next_indexed_frame().unwrap() {
+            sizes.push((f.width, f.height));
+        }
+        assert_eq!(sizes, [(16, 10), (6, 8), (4, 4), (5, 2)]);
+
+        let images = [sizes[0], sizes[2], sizes[3]];
+        for n in [2, 1, 0] {
+            decoder.seek_to_frame(&index, n).unwrap();
+            let f = decoder.next_indexed_frame().unwrap().unwrap();
+            assert_eq!((f.width, f.height), images[n]);
+        }
+
+        // Through `next_record` as well
+        decoder.seek_to_frame(&index, 1).unwrap();
+        decoder.next_indexed_frame().unwrap().unwrap();
+        let block = decoder.next_record().unwrap();
+        assert!(matches!(block, Block::Comment(t) if t == b"two"));
+        loop {
+            if let Block::Trailer = decoder.next_record().unwrap() {
+                break;
+            }
+        }
+
+        let comments: Vec<_> = decoder
+            .comments()
+            .iter()
+            .map(|c| (c.frame_index, c.bytes()))
+            .collect();
+        assert_eq!(comments, [(1, &b"one"[..]), (2, b"two")]);
+    }
+
+    #[test]
+    fn lzw_tables_allocated_once() {
+        let frames = [
+            TestFrame::new(0, 0, 16, 16),
+            TestFrame::new(2, 1, 12, 13),
+        ];
+        let data = test_gif::gif(16, 16, &[7; 24], &frames);
+        let mut decoder = Decoder::new(&data[..]).unwrap();
+        assert!(decoder.lzw_tables.is_none());
+
+        decoder.next_frame().unwrap().unwrap();
+        let tables: *const LzwTables =
+            &**decoder.lzw_tables.as_ref().unwrap();
+        decoder.next_frame().unwrap().unwrap();
+        let reused: *const LzwTables =
+            &**decoder.lzw_tables.as_ref().unwrap();
+        assert_eq!(tables, reused);
     }
 }
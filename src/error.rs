@@ -1,34 +1,380 @@
-use std::{fmt, io};
+use core::fmt;
 
-#[derive(Debug)]
-pub enum DecodingError {
-    Io(io::Error),
+use crate::{io, options::Limit};
+
+/// What went wrong, to tell failures apart without parsing messages
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ErrorKind {
+    /// The underlying reader failed, see `DecodingError::io_error`
+    Io,
+    /// The input ends inside a block
+    UnexpectedEof,
+    /// The input does not start with `GIF87a` or `GIF89a`
     InvalidSignature,
-    Format(String),
-    Unuspported(String),
+    /// A byte that starts no known block
+    UnknownBlock(u8),
+    /// An extension in a GIF87a file, in strict mode
+    ExtensionInGif87a,
+    /// A Graphic Control Extension whose block size is not 4
+    InvalidGceSize,
+    /// A Plain Text or Application Extension with an invalid block size
+    InvalidExtensionSize,
+    /// Reserved bits set in a Graphic Control Extension or an Image
+    /// Descriptor, in strict mode
+    ReservedBits,
+    /// A reserved disposal method, in strict mode
+    ReservedDisposal(u8),
+    /// A Graphic Control Extension without its block terminator, in
+    /// strict mode
+    MissingGceTerminator,
+    /// The input ends without a trailer, in strict mode
+    MissingTrailer,
+    /// A frame not contained in the logical screen, in strict mode
+    FrameOutsideScreen,
+    /// A frame without a Local Color Table in a GIF without a Global
+    /// Color Table
+    MissingPalette,
+    /// An LZW minimum code size above 11, or outside of 2 to 8 in
+    /// strict mode
+    InvalidMinCodeSize(u8),
+    /// A code not yet in the LZW dictionary
+    InvalidLzwCode,
+    /// The input ends inside the data sub-blocks of an image
+    TruncatedSubBlock,
+    /// The image data ends before the last pixel, in strict mode
+    MissingPixels { decoded: usize, expected: usize },
+    /// Data left after the last pixel, in strict mode
+    ExcessPixels,
+    /// Image data without an End Of Information code, in strict mode
+    MissingEndCode,
+    /// A color index beyond the palette, in strict mode
+    InvalidColorIndex { index: u8, palette_len: usize },
+    /// An ICC profile too short for its header
+    InvalidIccProfile,
+    /// A valid GIF using a feature the decoder does not implement
+    Unsupported(&'static str),
+    /// A limit set in `DecodeOptions` was reached
+    LimitExceeded(Limit),
+    /// The caller provided buffer does not fit the requested layout
+    InvalidBuffer { required: usize, len: usize },
+    /// Rows of `row_bytes` do not fit in the caller provided stride
+    InvalidStride { stride: usize, row_bytes: usize },
+    /// A frame index beyond the frames of a `FrameIndex`
+    FrameOutOfRange { frame: usize, len: usize },
+    /// Image data requested while the decoder is not at an image
+    NoImageData,
 }
 
-impl fmt::Display for DecodingError {
+impl fmt::Display for ErrorKind {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match self {
-            DecodingError::Io(err) => write!(f, "IO error: {}", err),
-            DecodingError::InvalidSignature => {
+        match *self {
+            ErrorKind::Io => write!(f, "IO error"),
+            ErrorKind::UnexpectedEof => {
+                write!(f, "Unexpected end of the input")
+            }
+            ErrorKind::InvalidSignature => {
                 write!(f, "Invalid GIF signature")
             }
-            DecodingError::Format(msg) => {
-                write!(f, "Format error: {}", msg)
+            ErrorKind::UnknownBlock(introducer) => {
+                write!(
+                    f,
+                    "Unknown block introducer: {:#04X}",
+                    introducer
+                )
+            }
+            ErrorKind::ExtensionInGif87a => {
+                write!(f, "Extension block in a GIF87a file")
+            }
+            ErrorKind::InvalidGceSize => {
+                write!(f, "Invalid GCE size")
+            }
+            ErrorKind::InvalidExtensionSize => {
+                write!(f, "Invalid extension size")
+            }
+            ErrorKind::ReservedBits => write!(f, "Reserved bits set"),
+            ErrorKind::ReservedDisposal(disposal) => {
+                write!(f, "Reserved disposal method {}", disposal)
+            }
+            ErrorKind::MissingGceTerminator => {
+                write!(f, "Missing GCE block terminator")
+            }
+            ErrorKind::MissingTrailer => write!(f, "Missing trailer"),
+            ErrorKind::FrameOutsideScreen => {
+                write!(f, "Frame outside of the logical screen")
+            }
+            ErrorKind::MissingPalette => {
+                write!(f, "No Global or Local palette found")
+            }
+            ErrorKind::InvalidMinCodeSize(size) => {
+                write!(f, "Invalid LZW minimum code size {}", size)
+            }
+            ErrorKind::InvalidLzwCode => {
+                write!(f, "Invalid LZW code")
+            }
+            ErrorKind::TruncatedSubBlock => {
+                write!(
+                    f,
+                    "GIF stream truncated inside a data sub-block"
+                )
+            }
+            ErrorKind::MissingPixels { decoded, expected } => write!(
+                f,
+                "Image data ends after {} of {} pixels",
+                decoded, expected
+            ),
+            ErrorKind::ExcessPixels => {
+                write!(f, "Image data overruns the frame")
+            }
+            ErrorKind::MissingEndCode => {
+                write!(
+                    f,
+                    "Image data without an End Of Information code"
+                )
+            }
+            ErrorKind::InvalidColorIndex { index, palette_len } => {
+                write!(
+                    f,
+                    "Color index {} beyond a palette of {} colors",
+                    index, palette_len
+                )
+            }
+            ErrorKind::InvalidIccProfile => {
+                write!(f, "Invalid ICC profile header")
+            }
+            ErrorKind::Unsupported(feature) => {
+                write!(f, "Unsupported feature: {}", feature)
+            }
+            ErrorKind::LimitExceeded(limit) => {
+                write!(f, "Decoding limit exceeded: {}", limit)
+            }
+            ErrorKind::InvalidBuffer { required, len } => write!(
+                f,
+                "Buffer too small. Requested {} bytes, received {}",
+                required, len
+            ),
+            ErrorKind::InvalidStride { stride, row_bytes } => write!(
+                f,
+                "Stride of {} bytes for rows of {} bytes",
+                stride, row_bytes
+            ),
+            ErrorKind::FrameOutOfRange { frame, len } => write!(
+                f,
+                "Frame {} out of range, the index has {} frames",
+                frame, len
+            ),
+            ErrorKind::NoImageData => {
+                write!(f, "No image data to decode")
+            }
+        }
+    }
+}
+
+/// A decoding failure, with where it happened in the GIF when known
+#[derive(Debug)]
+pub struct DecodingError {
+    kind: ErrorKind,
+    offset: Option<u64>,
+    frame: Option<usize>,
+    // The error of the reader or of the LZW decoder it comes from
+    source: Option<io::Error>,
+}
+
+impl DecodingError {
+    pub fn new(kind: ErrorKind) -> Self {
+        Self {
+            kind,
+            offset: None,
+            frame: None,
+            source: None,
+        }
+    }
+
+    pub fn kind(&self) -> ErrorKind {
+        self.kind
+    }
+
+    /// Offset in the input of the block where the error was found
+    ///
+    /// The image data is a block of its own, starting at its LZW
+    /// minimum code size. `None` for errors unrelated to the input,
+    /// such as an invalid buffer.
+    pub fn offset(&self) -> Option<u64> {
+        self.offset
+    }
+
+    /// Index of the frame being read: the last one whose Image
+    /// Descriptor was found, `None` before the first one
+    pub fn frame(&self) -> Option<usize> {
+        self.frame
+    }
+
+    /// The error of the underlying reader, if any
+    pub fn io_error(&self) -> Option<&io::Error> {
+        self.source.as_ref()
+    }
+
+    /// Sets where the error happened, unless already known
+    pub(crate) fn at(
+        mut self,
+        offset: u64,
+        frame: Option<usize>,
+    ) -> Self {
+        if self.offset.is_none() {
+            self.offset = Some(offset);
+            self.frame = frame;
+        }
+        self
+    }
+
+    /// Classifies an error of the LZW decoder or of the image data
+    /// sub-blocks
+    pub(crate) fn image_data(err: io::Error) -> Self {
+        let kind = match err.kind() {
+            io::ErrorKind::InvalidData => ErrorKind::InvalidLzwCode,
+            io::ErrorKind::UnexpectedEof => {
+                ErrorKind::TruncatedSubBlock
             }
-            DecodingError::Unuspported(msg) => {
-                write!(f, "Unsupported feature: {}", msg)
+            _ => ErrorKind::Io,
+        };
+        Self {
+            source: Some(err),
+            ..Self::new(kind)
+        }
+    }
+}
+
+impl fmt::Display for DecodingError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (&self.kind, &self.source) {
+            (ErrorKind::Io, Some(err)) => {
+                write!(f, "IO error: {}", err)?
             }
+            (kind, _) => write!(f, "{}", kind)?,
+        }
+        if let Some(offset) = self.offset {
+            write!(f, " at byte {}", offset)?;
+        }
+        if let Some(frame) = self.frame {
+            write!(f, " in frame {}", frame)?;
+        }
+        Ok(())
+    }
+}
+
+impl core::error::Error for DecodingError {
+    fn source(&self) -> Option<&(dyn core::error::Error + 'static)> {
+        match &self.source {
+            Some(err) => Some(err),
+            None => None,
         }
     }
 }
 
-impl std::error::Error for DecodingError {}
+impl From<ErrorKind> for DecodingError {
+    fn from(kind: ErrorKind) -> Self {
+        Self::new(kind)
+    }
+}
 
 impl From<io::Error> for DecodingError {
     fn from(err: io::Error) -> Self {
-        DecodingError::Io(err)
+        let kind = match err.kind() {
+            io::ErrorKind::UnexpectedEof => ErrorKind::UnexpectedEof,
+            _ => ErrorKind::Io,
+        };
+        Self {
+            source: Some(err),
+            ..Self::new(kind)
+        }
+    }
+}
+
+/// A defect of the GIF that the decoder worked around
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum WarningKind {
+    /// The input ends without a trailer, in lenient mode
+    MissingTrailer,
+    /// A frame not contained in the logical screen, its pixels outside
+    /// of the screen are not drawn
+    FrameOutsideScreen,
+    /// A color index beyond the palette, drawn black
+    ///
+    /// Only the first one of each frame is reported.
+    InvalidColorIndex { index: u8, palette_len: usize },
+    /// Image data left after the last pixel of a frame or after the
+    /// End Of Information code, ignored
+    ExcessImageData,
+}
+
+impl fmt::Display for WarningKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            WarningKind::MissingTrailer => {
+                write!(f, "Missing trailer")
+            }
+            WarningKind::FrameOutsideScreen => {
+                write!(f, "Frame outside of the logical screen")
+            }
+            WarningKind::InvalidColorIndex { index, palette_len } => {
+                write!(
+                    f,
+                    "Color index {} beyond a palette of {} colors",
+                    index, palette_len
+                )
+            }
+            WarningKind::ExcessImageData => {
+                write!(f, "Image data left after the last pixel")
+            }
+        }
+    }
+}
+
+/// A non fatal decoding issue, with where it was found, see
+/// `Decoder::warnings`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Warning {
+    kind: WarningKind,
+    offset: u64,
+    frame: Option<usize>,
+}
+
+impl Warning {
+    pub(crate) fn new(
+        kind: WarningKind,
+        offset: u64,
+        frame: Option<usize>,
+    ) -> Self {
+        Self {
+            kind,
+            offset,
+            frame,
+        }
+    }
+
+    pub fn kind(&self) -> WarningKind {
+        self.kind
+    }
+
+    /// Offset in the input of the block where the issue was found, as
+    /// for `DecodingError::offset`
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    /// Index of the frame being read, as for `DecodingError::frame`
+    pub fn frame(&self) -> Option<usize> {
+        self.frame
+    }
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at byte {}", self.kind, self.offset)?;
+        if let Some(frame) = self.frame {
+            write!(f, " in frame {}", frame)?;
+        }
+        Ok(())
     }
 }
@@ -1,13 +1,69 @@
-use crate::{render::GifColor, structs::DisposalMethod};
+use alloc::{sync::Arc, vec::Vec};
 
+use crate::{
+    pixel::{GifColor, Pixel},
+    structs::{DisposalMethod, Palette},
+};
+
+#[derive(Debug, Clone)]
+pub struct Frame<P: Pixel = GifColor> {
+    pub delay_cs: u16,
+    pub disposal: DisposalMethod,
+    pub left: u16,
+    pub top: u16,
+    pub width: u16,
+    pub height: u16,
+    pub pixels: Vec<P>,
+    pub transparent_index: Option<u8>,
+    /// False if the image data ended before every pixel was decoded
+    pub complete: bool,
+}
+
+/// A frame as color indices, see `Decoder::next_indexed_frame`
 #[derive(Debug, Clone)]
-pub struct Frame {
+pub struct IndexedFrame {
+    pub delay_cs: u16,
+    pub disposal: DisposalMethod,
+    pub left: u16,
+    pub top: u16,
+    pub width: u16,
+    pub height: u16,
+    pub indices: Vec<u8>,
+    /// The Local palette, or the Global palette shared with the
+    /// decoder and the other frames
+    pub palette: Arc<Palette>,
+    pub transparent_index: Option<u8>,
+    /// False if the image data ended before every pixel was decoded
+    pub complete: bool,
+}
+
+impl IndexedFrame {
+    /// Returns the frame without its indices and palette
+    pub fn info(&self) -> FrameInfo {
+        FrameInfo {
+            delay_cs: self.delay_cs,
+            disposal: self.disposal,
+            left: self.left,
+            top: self.top,
+            width: self.width,
+            height: self.height,
+            transparent_index: self.transparent_index,
+            complete: self.complete,
+        }
+    }
+}
+
+/// A frame decoded into a caller provided buffer, see
+/// `Decoder::next_frame_into`
+#[derive(Debug, Clone, Copy)]
+pub struct FrameInfo {
     pub delay_cs: u16,
     pub disposal: DisposalMethod,
     pub left: u16,
     pub top: u16,
     pub width: u16,
     pub height: u16,
-    pub pixels: Vec<GifColor>,
     pub transparent_index: Option<u8>,
+    /// False if the image data ended before every pixel was decoded
+    pub complete: bool,
 }
@@ -0,0 +1,293 @@
+use crate::{
+    error::{DecodingError, ErrorKind},
+    structs::{Color, Palette},
+};
+
+const HEADER_SIZE: usize = 128;
+
+/// XYZ (D50 PCS) to linear sRGB, with Bradford adaptation to D65
+const XYZ_D50_TO_SRGB: [[f32; 3]; 3] = [
+    [3.133856, -1.616867, -0.4906146],
+    [-0.9787684, 1.916142, 0.033454],
+    [0.0719453, -0.2289914, 1.405243],
+];
+
+/// Tone reproduction curve of a single channel
+#[derive(Debug, Clone, PartialEq)]
+pub enum Curve {
+    /// `curv` with no entries
+    Identity,
+    /// `curv` with a single entry
+    Gamma(f32),
+    /// `curv` with a sampled table, normalized to [0, 1]
+    Table(Vec<f32>),
+    /// `para`: the piecewise function `Y = (aX + b)^g + e` if
+    /// `X >= d`, `Y = cX + f` otherwise
+    Parametric {
+        g: f32,
+        a: f32,
+        b: f32,
+        c: f32,
+        d: f32,
+        e: f32,
+        f: f32,
+    },
+}
+
+impl Curve {
+    pub fn eval(&self, x: f32) -> f32 {
+        match self {
+            Curve::Identity => x,
+            Curve::Gamma(g) => x.powf(*g),
+            Curve::Table(table) => {
+                let pos = x.clamp(0.0, 1.0) * (table.len() - 1) as f32;
+                let i = pos.floor() as usize;
+                let frac = pos - i as f32;
+                match table.get(i + 1) {
+                    Some(next) => table[i] + (next - table[i]) * frac,
+                    None => table[i],
+                }
+            }
+            Curve::Parametric { g, a, b, c, d, e, f } => {
+                if x >= *d {
+                    (a * x + b).max(0.0).powf(*g) + e
+                } else {
+                    c * x + f
+                }
+            }
+        }
+    }
+}
+
+/// A matrix/TRC RGB ICC profile, as embedded by the ICCRGBG1
+/// application extension
+#[derive(Debug, Clone, PartialEq)]
+pub struct IccProfile {
+    /// Columns are the rXYZ, gXYZ and bXYZ colorants
+    pub matrix: [[f32; 3]; 3],
+    pub curves: [Curve; 3],
+}
+
+impl IccProfile {
+    /// Parses the header and the tag table of an ICC profile
+    ///
+    /// Only RGB profiles described by colorants and tone curves are
+    /// supported, LUT based profiles are reported as unsupported.
+    pub fn parse(data: &[u8]) -> Result<Self, DecodingError> {
+        if data.len() < HEADER_SIZE + 4 || &data[36..40] != b"acsp" {
+            return Err(ErrorKind::InvalidIccProfile.into());
+        }
+
+        if &data[16..20] != b"RGB " || &data[20..24] != b"XYZ " {
+            return Err(ErrorKind::Unsupported(
+                "ICC profile is not RGB with an XYZ connection space",
+            )
+            .into());
+        }
+
+        let red = find_tag(data, b"rXYZ").and_then(parse_xyz);
+        let green = find_tag(data, b"gXYZ").and_then(parse_xyz);
+        let blue = find_tag(data, b"bXYZ").and_then(parse_xyz);
+
+        let (Some(red), Some(green), Some(blue)) = (red, green, blue)
+        else {
+            return Err(ErrorKind::Unsupported(
+                "ICC profile without matrix colorants",
+            )
+            .into());
+        };
+
+        let red_trc = find_tag(data, b"rTRC").and_then(parse_curve);
+        let green_trc = find_tag(data, b"gTRC").and_then(parse_curve);
+        let blue_trc = find_tag(data, b"bTRC").and_then(parse_curve);
+
+        let (Some(red_trc), Some(green_trc), Some(blue_trc)) =
+            (red_trc, green_trc, blue_trc)
+        else {
+            return Err(ErrorKind::Unsupported(
+                "ICC profile without tone reproduction curves",
+            )
+            .into());
+        };
+
+        let mut matrix = [[0.0; 3]; 3];
+        for (row, m) in matrix.iter_mut().enumerate() {
+            *m = [red[row], green[row], blue[row]];
+        }
+
+        Ok(Self {
+            matrix,
+            curves: [red_trc, green_trc, blue_trc],
+        })
+    }
+
+    /// Converts a color from the profile color space to sRGB
+    pub fn to_srgb(&self, color: Color) -> Color {
+        let linear = [
+            self.curves[0].eval(color.r as f32 / 255.0),
+            self.curves[1].eval(color.g as f32 / 255.0),
+            self.curves[2].eval(color.b as f32 / 255.0),
+        ];
+
+        let xyz = mul(&self.matrix, linear);
+        let [r, g, b] = mul(&XYZ_D50_TO_SRGB, xyz);
+
+        Color {
+            r: encode_srgb(r),
+            g: encode_srgb(g),
+            b: encode_srgb(b),
+        }
+    }
+
+    pub fn convert_palette(&self, palette: &Palette) -> Palette {
+        palette.iter().map(|&c| self.to_srgb(c)).collect()
+    }
+}
+
+fn mul(m: &[[f32; 3]; 3], v: [f32; 3]) -> [f32; 3] {
+    let mut out = [0.0; 3];
+    for (o, row) in out.iter_mut().zip(m) {
+        *o = row[0] * v[0] + row[1] * v[1] + row[2] * v[2];
+    }
+    out
+}
+
+fn encode_srgb(v: f32) -> u8 {
+    let v = v.clamp(0.0, 1.0);
+    let encoded = if v <= 0.0031308 {
+        12.92 * v
+    } else {
+        1.055 * v.powf(1.0 / 2.4) - 0.055
+    };
+    (encoded * 255.0).round() as u8
+}
+
+fn read_u32(data: &[u8], at: usize) -> Option<u32> {
+    let bytes = data.get(at..at + 4)?;
+    Some(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+fn read_u16(data: &[u8], at: usize) -> Option<u16> {
+    let bytes = data.get(at..at + 2)?;
+    Some(u16::from_be_bytes([bytes[0], bytes[1]]))
+}
+
+fn read_s15_fixed16(data: &[u8], at: usize) -> Option<f32> {
+    Some(read_u32(data, at)? as i32 as f32 / 65536.0)
+}
+
+/// Returns the data of the tag with the given signature
+fn find_tag<'a>(data: &'a [u8], signature: &[u8; 4]) -> Option<&'a [u8]> {
+    let count = read_u32(data, HEADER_SIZE)? as usize;
+    // The count is untrusted, only the entries in `data` can match
+    let count = count.min((data.len() - HEADER_SIZE - 4) / 12);
+
+    (0..count).find_map(|i| {
+        let entry = HEADER_SIZE + 4 + i * 12;
+        if data.get(entry..entry + 4)? != signature {
+            return None;
+        }
+        let offset = read_u32(data, entry + 4)? as usize;
+        let size = read_u32(data, entry + 8)? as usize;
+        data.get(offset..offset.checked_add(size)?)
+    })
+}
+
+fn parse_xyz(tag: &[u8]) -> Option<[f32; 3]> {
+    if tag.get(0..4)? != b"XYZ " {
+        return None;
+    }
+    Some([
+        read_s15_fixed16(tag, 8)?,
+        read_s15_fixed16(tag, 12)?,
+        read_s15_fixed16(tag, 16)?,
+    ])
+}
+
+fn parse_curve(tag: &[u8]) -> Option<Curve> {
+    match tag.get(0..4)? {
+        b"curv" => {
+            let count = read_u32(tag, 8)? as usize;
+            match count {
+                0 => Some(Curve::Identity),
+                1 => Some(Curve::Gamma(read_u16(tag, 12)? as f32 / 256.0)),
+                _ => (0..count)
+                    .map(|i| {
+                        read_u16(tag, 12 + i * 2)
+                            .map(|v| v as f32 / 65535.0)
+                    })
+                    .collect::<Option<Vec<_>>>()
+                    .map(Curve::Table),
+            }
+        }
+        b"para" => {
+            let function = read_u16(tag, 8)?;
+            let param_count = match function {
+                0 => 1,
+                1 => 3,
+                2 => 4,
+                3 => 5,
+                4 => 7,
+                _ => return None,
+            };
+
+            let mut p = [0.0f32; 7];
+            for (i, v) in p.iter_mut().take(param_count).enumerate() {
+                *v = read_s15_fixed16(tag, 12 + i * 4)?;
+            }
+            let [g, a, b, c, d, e, f] = p;
+
+            Some(match function {
+                0 => Curve::Gamma(g),
+                // Y = (aX + b)^g if X >= -b/a, 0 otherwise
+                1 => Curve::Parametric {
+                    g,
+                    a,
+                    b,
+                    c: 0.0,
+                    d: -b / a,
+                    e: 0.0,
+                    f: 0.0,
+                },
+                // Y = (aX + b)^g + c if X >= -b/a, c otherwise
+                2 => Curve::Parametric {
+                    g,
+                    a,
+                    b,
+                    c: 0.0,
+                    d: -b / a,
+                    e: c,
+                    f: c,
+                },
+                3 => Curve::Parametric {
+                    g,
+                    a,
+                    b,
+                    c,
+                    d,
+                    e: 0.0,
+                    f: 0.0,
+                },
+                _ => Curve::Parametric { g, a, b, c, d, e, f },
+            })
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tag_count_is_bounded_by_the_data() {
+        let mut data = vec![0; HEADER_SIZE + 4 + 12];
+        data[16..20].copy_from_slice(b"RGB ");
+        data[20..24].copy_from_slice(b"XYZ ");
+        data[36..40].copy_from_slice(b"acsp");
+        data[HEADER_SIZE..HEADER_SIZE + 4].fill(0xFF);
+
+        assert_eq!(find_tag(&data, b"rXYZ"), None);
+        assert!(IccProfile::parse(&data).is_err());
+    }
+}
@@ -0,0 +1,128 @@
+use alloc::vec::Vec;
+
+use crate::structs::{
+    DisposalMethod, GraphicControl, ImageDescriptor,
+    LogicalScreenDescriptor,
+};
+
+/// Where the palette of a frame comes from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PaletteSource {
+    Global,
+    /// Offset of the Local Color Table
+    Local(u64),
+}
+
+/// Position and parameters of a single frame
+///
+/// Offsets are counted from the first byte of the GIF signature.
+#[derive(Debug, Clone)]
+pub struct FrameIndexEntry {
+    /// Offset of the Image Separator (0x2C)
+    pub offset: u64,
+    /// Offset of the Extension Introducer of the Graphic Control
+    /// Extension that applies to the frame
+    pub control_offset: Option<u64>,
+    pub control: Option<GraphicControl>,
+    pub descriptor: ImageDescriptor,
+    pub palette: PaletteSource,
+}
+
+impl FrameIndexEntry {
+    /// Offset from which `Decoder::next_frame` decodes this frame
+    /// with its Graphic Control Extension
+    pub fn start_offset(&self) -> u64 {
+        self.control_offset.unwrap_or(self.offset)
+    }
+
+    fn disposal(&self) -> DisposalMethod {
+        self.control.map(|c| c.disposal_method).unwrap_or_default()
+    }
+
+    fn has_transparency(&self) -> bool {
+        self.control
+            .is_some_and(|c| c.transparent_color_index.is_some())
+    }
+
+    fn covers(&self, screen: &LogicalScreenDescriptor) -> bool {
+        let d = &self.descriptor;
+        d.left == 0
+            && d.top == 0
+            && d.width >= screen.width
+            && d.height >= screen.height
+    }
+}
+
+/// Byte offsets of every frame of a GIF, built by
+/// `Decoder::build_frame_index` with a structural scan that skips the
+/// compressed data
+///
+/// Plain Text Extensions are not indexed, even when the decoder
+/// renders them as frames.
+#[derive(Debug, Clone)]
+pub struct FrameIndex {
+    screen_descriptor: LogicalScreenDescriptor,
+    frames: Vec<FrameIndexEntry>,
+}
+
+impl FrameIndex {
+    pub(crate) fn new(
+        screen_descriptor: LogicalScreenDescriptor,
+        frames: Vec<FrameIndexEntry>,
+    ) -> Self {
+        Self {
+            screen_descriptor,
+            frames,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.frames.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
+
+    pub fn get(&self, n: usize) -> Option<&FrameIndexEntry> {
+        self.frames.get(n)
+    }
+
+    pub fn frames(&self) -> &[FrameIndexEntry] {
+        &self.frames
+    }
+
+    /// Returns true if compositing can start from frame `n` on a
+    /// canvas cleared to the background
+    ///
+    /// That is the case for the first frame, for an opaque frame that
+    /// covers the whole canvas (and is not restored to the previous
+    /// canvas afterwards), and for a frame that follows one covering the
+    /// whole canvas and restored to the background.
+    pub fn is_key_frame(&self, n: usize) -> bool {
+        if n == 0 {
+            return true;
+        }
+
+        let screen = &self.screen_descriptor;
+
+        let Some(frame) = self.frames.get(n) else {
+            return false;
+        };
+        if frame.covers(screen)
+            && !frame.has_transparency()
+            && frame.disposal() != DisposalMethod::RestorePrevious
+        {
+            return true;
+        }
+
+        let previous = &self.frames[n - 1];
+        previous.covers(screen)
+            && previous.disposal() == DisposalMethod::RestoreBackground
+    }
+
+    /// Returns the nearest key frame at or before frame `n`
+    pub fn key_frame_for(&self, n: usize) -> usize {
+        (0..=n).rev().find(|&i| self.is_key_frame(i)).unwrap_or(0)
+    }
+}
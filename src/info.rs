@@ -0,0 +1,102 @@
+use crate::{
+    decoder::{Block, Decoder},
+    error::DecodingError,
+    io::Read,
+    structs::{LoopCount, Version},
+};
+
+/// Summary of a GIF, collected without decompressing the frames
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GifInfo {
+    pub version: Version,
+    pub width: u16,
+    pub height: u16,
+    pub frame_count: usize,
+    /// Sum of the frame delays, in 1/100 seconds
+    pub total_duration_cs: u64,
+    pub loop_count: Option<LoopCount>,
+    /// True if at least one frame declares a transparent color index
+    pub has_transparency: bool,
+}
+
+/// Walks the blocks of a GIF, skipping the image data sub-blocks
+/// instead of running the LZW decoder
+pub fn probe<R: Read>(reader: R) -> Result<GifInfo, DecodingError> {
+    let mut decoder = Decoder::new(reader)?;
+
+    let mut info = GifInfo {
+        version: decoder.version,
+        width: decoder.screen_descriptor.width,
+        height: decoder.screen_descriptor.height,
+        frame_count: 0,
+        total_duration_cs: 0,
+        loop_count: None,
+        has_transparency: false,
+    };
+
+    let mut control = None;
+
+    loop {
+        match decoder.next_record()? {
+            Block::GraphicControl(ext) => control = Some(ext),
+            Block::ImageDescriptor(..) => {
+                if let Some(ext) = control.take() {
+                    info.total_duration_cs += ext.delay_time_cs as u64;
+                    info.has_transparency |=
+                        ext.transparent_color_index.is_some();
+                }
+                info.frame_count += 1;
+            }
+            Block::PlainText(_) => control = None,
+            Block::Trailer => break,
+            _ => {}
+        }
+    }
+
+    info.loop_count = decoder.loop_count();
+
+    Ok(info)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_gif::{self, TestFrame};
+
+    #[test]
+    fn summary_of_an_animation() {
+        let data = test_gif::animation();
+        let expected = GifInfo {
+            version: Version::Gif89a,
+            width: 40,
+            height: 30,
+            frame_count: 4,
+            total_duration_cs: 40,
+            loop_count: Some(LoopCount::Infinite),
+            has_transparency: true,
+        };
+        assert_eq!(probe(&data[..]).unwrap(), expected);
+    }
+
+    /// The image data is skipped without being decompressed
+    #[test]
+    fn invalid_image_data_is_skipped() {
+        let mut frame = TestFrame::new(0, 0, 4, 4);
+        frame.delay_cs = 25;
+        let mut data = test_gif::gif(4, 4, &[0; 24], &[frame]);
+
+        // Content of the first image data sub-block, after the
+        // header, the palette, the NETSCAPE2.0 extension, the Graphic
+        // Control Extension and the image descriptor
+        let start = 13 + 24 + 19 + 8 + 10 + 2;
+        let len = data[start - 1] as usize;
+        data[start..start + len].fill(0xFF);
+        let mut decoder = Decoder::new(&data[..]).unwrap();
+        assert!(decoder.next_frame().is_err());
+
+        let info = probe(&data[..]).unwrap();
+        assert_eq!(info.frame_count, 1);
+        assert_eq!(info.total_duration_cs, 25);
+        assert!(!info.has_transparency);
+    }
+}
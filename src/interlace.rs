@@ -0,0 +1,53 @@
+const PASS_STARTS: [usize; 4] = [0, 4, 2, 1];
+const PASS_STEPS: [usize; 4] = [8, 8, 4, 2];
+
+/// Destination row of each row of an image, in the order they are
+/// stored in the compressed data
+///
+/// Interlaced images are stored in four passes: every 8th row from
+/// row 0, every 8th row from row 4, every 4th row from row 2 and every
+/// 2nd row from row 1.
+pub struct RowOrder {
+    height: usize,
+    interlaced: bool,
+    pass: usize,
+    y: usize,
+}
+
+impl RowOrder {
+    pub fn new(height: usize, interlaced: bool) -> Self {
+        Self {
+            height,
+            interlaced,
+            pass: 0,
+            y: 0,
+        }
+    }
+}
+
+impl Iterator for RowOrder {
+    type Item = usize;
+
+    fn next(&mut self) -> Option<usize> {
+        if !self.interlaced {
+            let y = self.y;
+            if y >= self.height {
+                return None;
+            }
+            self.y += 1;
+            return Some(y);
+        }
+
+        while self.y >= self.height {
+            if self.pass == 3 {
+                return None;
+            }
+            self.pass += 1;
+            self.y = PASS_STARTS[self.pass];
+        }
+
+        let y = self.y;
+        self.y += PASS_STEPS[self.pass];
+        Some(y)
+    }
+}
@@ -0,0 +1,11 @@
+//! Byte sources read by the decoder
+//!
+//! With the `std` feature these are the items of `std::io`. Without
+//! it, minimal replacements are provided: a `Decoder` reads any type
+//! implementing `Read`, already implemented for `&[u8]`.
+
+#[cfg(feature = "std")]
+pub use std::io::{BufRead, Error, ErrorKind, Read, Result};
+
+#[cfg(not(feature = "std"))]
+pub use crate::core_io::{BufRead, Error, ErrorKind, Read, Result};
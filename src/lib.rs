@@ -1,10 +1,48 @@
+//! A GIF (GIF89a) decoder written in pure Rust
+//!
+//! # Features
+//!
+//! - `std`, enabled by default: reads from `std::io` types and adds
+//!   decoding on several threads (`DecodeOptions::threads`), color
+//!   management with the embedded ICC profile (`icc`,
+//!   `Decoder::set_apply_color_profile`) and seeking (`index`,
+//!   `Decoder::build_frame_index`, `seek_to_frame`).
+//!
+//! Without `std` the crate is `no_std` and needs `alloc`. Every
+//! decoder is available, frames are decoded on the calling thread and
+//! the ICC profile is only returned as bytes by
+//! `Decoder::icc_profile`: its curves need the floating point
+//! functions of `std`, and the frame index needs `std::io::Seek`.
+
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
+
 pub mod error;
 pub mod structs;
 pub mod decoder;
 pub mod frame;
 pub mod animator;
+pub mod arena;
+#[cfg(feature = "std")]
+pub mod icc;
+#[cfg(feature = "std")]
+pub mod index;
+pub mod info;
+pub mod io;
+pub mod options;
+pub mod pixel;
+pub mod streaming;
 
 mod bitreader;
+#[cfg(not(feature = "std"))]
+mod core_io;
+mod font;
+mod interlace;
+mod parallel;
 mod reader;
 mod render;
+mod scale;
 mod lzw;
+#[cfg(test)]
+mod test_gif;
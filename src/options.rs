@@ -0,0 +1,266 @@
+use core::fmt;
+
+/// A decoding limit, reported by `ErrorKind::LimitExceeded`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Limit {
+    CanvasPixels,
+    FramePixels,
+    Frames,
+    TotalBytes,
+    ExtensionBytes,
+}
+
+impl fmt::Display for Limit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Limit::CanvasPixels => "canvas pixels",
+            Limit::FramePixels => "frame pixels",
+            Limit::Frames => "frame count",
+            Limit::TotalBytes => "total allocated bytes",
+            Limit::ExtensionBytes => "extension bytes",
+        };
+        f.write_str(name)
+    }
+}
+
+/// Limits enforced while decoding, to protect against decompression
+/// bombs. Every limit is disabled by default.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Limits {
+    /// Pixels of the logical screen, i.e. of the `GifStream` canvas
+    pub max_canvas_pixels: Option<u64>,
+    /// Pixels of a single frame
+    pub max_frame_pixels: Option<u64>,
+    pub max_frames: Option<usize>,
+    /// Bytes allocated for palettes, extension data, frame and canvas
+    /// buffers, summed over the whole decoding
+    pub max_total_bytes: Option<u64>,
+    /// Data bytes of a single extension block
+    pub max_extension_bytes: Option<usize>,
+}
+
+/// How the decoder reacts to data that does not follow the spec
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DecodeMode {
+    /// Corrupt or truncated data is reported as an error, except for
+    /// LZW data that stops before the end of the frame: the frame is
+    /// returned incomplete
+    #[default]
+    Standard,
+    /// Corrupt or truncated frames are returned as far as they could be
+    /// decoded, marked as incomplete, and a truncated file ends after
+    /// its last frame, as browsers display half-downloaded GIFs
+    ///
+    /// The blocks other than the image data are not recovered: a file
+    /// truncated inside an extension or an Image Descriptor is still
+    /// reported as an error.
+    Lenient,
+    /// Anything the GIF89a spec disallows is reported as an error:
+    /// missing trailer, frames outside of the logical screen, color
+    /// indices beyond the palette, invalid LZW minimum code sizes,
+    /// image data that does not fill the frame exactly, extensions in
+    /// GIF87a files and reserved bits set
+    Strict,
+}
+
+/// How the missing pixels of an incomplete frame are filled in lenient
+/// mode
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum IncompleteFill {
+    /// Missing pixels are transparent: the canvas below shows through
+    #[default]
+    Transparent,
+    /// Missing pixels repeat the nearest decoded row above them
+    LastRow,
+}
+
+/// Downscaling applied while decoding
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Scale {
+    #[default]
+    Full,
+    Half,
+    Quarter,
+    Eighth,
+}
+
+impl Scale {
+    /// Returns the number of screen pixels per scaled pixel, in each
+    /// direction
+    pub fn factor(self) -> usize {
+        match self {
+            Scale::Full => 1,
+            Scale::Half => 2,
+            Scale::Quarter => 4,
+            Scale::Eighth => 8,
+        }
+    }
+}
+
+/// How scaled pixels are computed from the screen pixels they cover
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ScaleFilter {
+    /// Average of the covered pixels, transparent pixels lower the
+    /// alpha
+    #[default]
+    Box,
+    /// The top left covered pixel
+    Point,
+}
+
+/// A rectangle on the logical screen
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Region {
+    pub left: u16,
+    pub top: u16,
+    pub width: u16,
+    pub height: u16,
+}
+
+impl Region {
+    pub fn new(left: u16, top: u16, width: u16, height: u16) -> Self {
+        Self {
+            left,
+            top,
+            width,
+            height,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.width == 0 || self.height == 0
+    }
+
+    /// Returns the overlap of two regions
+    /// If they do not overlap the result has a width and a height of
+    /// 0, positioned at the largest left and top of the two.
+    pub fn intersect(&self, other: &Region) -> Region {
+        let left = self.left.max(other.left);
+        let top = self.top.max(other.top);
+        let right = self.right().min(other.right());
+        let bottom = self.bottom().min(other.bottom());
+
+        let width = right.saturating_sub(left as u32) as u16;
+        let height = bottom.saturating_sub(top as u32) as u16;
+        if width == 0 || height == 0 {
+            return Region::new(left, top, 0, 0);
+        }
+
+        Region {
+            left,
+            top,
+            width,
+            height,
+        }
+    }
+
+    fn right(&self) -> u32 {
+        self.left as u32 + self.width as u32
+    }
+
+    fn bottom(&self) -> u32 {
+        self.top as u32 + self.height as u32
+    }
+}
+
+/// Options of `Decoder::with_options`
+#[derive(Debug, Clone, Default)]
+pub struct DecodeOptions {
+    pub(crate) limits: Limits,
+    pub(crate) mode: DecodeMode,
+    pub(crate) incomplete_fill: IncompleteFill,
+    pub(crate) region: Option<Region>,
+    pub(crate) scale: Scale,
+    pub(crate) scale_filter: ScaleFilter,
+    pub(crate) threads: usize,
+}
+
+impl DecodeOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn mode(mut self, mode: DecodeMode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    pub fn incomplete_fill(mut self, fill: IncompleteFill) -> Self {
+        self.incomplete_fill = fill;
+        self
+    }
+
+    /// Only keeps the pixels inside `region`
+    ///
+    /// Frames are cropped to the region and the `GifStream` canvas
+    /// covers the region only. The whole image data is still
+    /// decompressed. `IncompleteFill::LastRow` only repeats rows inside
+    /// the region.
+    pub fn region(mut self, region: Region) -> Self {
+        self.region = Some(region);
+        self
+    }
+
+    /// Downscales frames and canvases while decoding
+    ///
+    /// Scaled frames and canvases are clipped to the logical screen, or
+    /// to the region if one is set, and their coordinates are in scaled
+    /// pixels. The missing pixels of incomplete frames are always
+    /// transparent. Indexed frames and `Decoder::next_frame_into` do
+    /// not support scaling.
+    pub fn scale(mut self, scale: Scale) -> Self {
+        self.scale = scale;
+        self
+    }
+
+    pub fn scale_filter(mut self, filter: ScaleFilter) -> Self {
+        self.scale_filter = filter;
+        self
+    }
+
+    /// Decompresses frames on `threads` threads
+    ///
+    /// A few frames per thread are read ahead, decompressed in parallel
+    /// and returned in order by `Decoder::next_frame`,
+    /// `Decoder::next_indexed_frame` and `GifStream`. The threads are
+    /// started once and kept until the decoder is dropped. 0 and 1
+    /// decode on the calling thread. Scaled decoding and
+    /// `Decoder::next_frame_into` are not parallel: mixed with the
+    /// other methods, like `Decoder::next_record`, they continue after
+    /// the frames read ahead. Requires the `std` feature.
+    #[cfg(feature = "std")]
+    pub fn threads(mut self, threads: usize) -> Self {
+        self.threads = threads;
+        self
+    }
+
+    pub fn limits(mut self, limits: Limits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    pub fn max_canvas_pixels(mut self, max: u64) -> Self {
+        self.limits.max_canvas_pixels = Some(max);
+        self
+    }
+
+    pub fn max_frame_pixels(mut self, max: u64) -> Self {
+        self.limits.max_frame_pixels = Some(max);
+        self
+    }
+
+    pub fn max_frames(mut self, max: usize) -> Self {
+        self.limits.max_frames = Some(max);
+        self
+    }
+
+    pub fn max_total_bytes(mut self, max: u64) -> Self {
+        self.limits.max_total_bytes = Some(max);
+        self
+    }
+
+    pub fn max_extension_bytes(mut self, max: usize) -> Self {
+        self.limits.max_extension_bytes = Some(max);
+        self
+    }
+}
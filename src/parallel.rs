@@ -0,0 +1,260 @@
+use alloc::{vec, vec::Vec};
+#[cfg(feature = "std")]
+use std::{
+    boxed::Box,
+    panic::{self, AssertUnwindSafe},
+    sync::{Arc, Mutex, mpsc},
+    thread,
+};
+
+use crate::{
+    decoder::{self, CropRows, IndexRows},
+    error::{DecodingError, ErrorKind},
+    lzw::{LzwDecoder, LzwTables},
+    options::{DecodeMode, Region},
+    structs::ImageDescriptor,
+};
+
+/// The compressed data of a frame, read ahead of its decompression
+pub struct ImageJob {
+    pub descriptor: ImageDescriptor,
+    /// Part of the frame that is kept, in frame coordinates
+    pub crop: Region,
+    /// Where the image data starts and the index of the frame, to
+    /// locate errors
+    pub offset: u64,
+    pub frame: usize,
+    /// `None` if the data ends before the LZW Minimum Code Size
+    pub min_code_size: Option<u8>,
+    /// Content of the data sub-blocks
+    pub data: Vec<u8>,
+    /// The data ends inside a sub-block
+    pub truncated: bool,
+}
+
+/// The cropped indices of a frame, how many pixels have been decoded
+/// and whether data is left after the last pixel
+pub type JobOutput = Result<(Vec<u8>, usize, bool), DecodingError>;
+
+impl ImageJob {
+    /// Decompresses the frame, see `Decoder::decode_rows`
+    fn decode(
+        &self,
+        mode: DecodeMode,
+        tables: &mut LzwTables,
+    ) -> JobOutput {
+        let width = self.descriptor.width as usize;
+        let pixel_count =
+            self.crop.width as usize * self.crop.height as usize;
+        let mut indices = vec![0u8; pixel_count];
+
+        let Some(min_code_size) = self.min_code_size else {
+            return Ok((indices, 0, false));
+        };
+
+        let data = self.data.as_slice();
+        let (decoded, excess) =
+            if decoder::crop_is_whole(&self.crop, &self.descriptor) {
+                decoder::decode_image_data(
+                    LzwDecoder::with_tables(
+                        data,
+                        min_code_size,
+                        &mut *tables,
+                    ),
+                    &self.descriptor,
+                    mode,
+                    &mut IndexRows {
+                        buffer: &mut indices,
+                        width,
+                    },
+                )?
+            } else {
+                let mut row = vec![0u8; width];
+                decoder::decode_image_data(
+                    LzwDecoder::with_tables(
+                        data,
+                        min_code_size,
+                        &mut *tables,
+                    ),
+                    &self.descriptor,
+                    mode,
+                    &mut CropRows {
+                        row: &mut row,
+                        buffer: &mut indices,
+                        crop: self.crop,
+                    },
+                )?
+            };
+
+        if self.truncated && mode != DecodeMode::Lenient {
+            return Err(ErrorKind::TruncatedSubBlock.into());
+        }
+
+        Ok((indices, decoded, excess))
+    }
+}
+
+/// Decompresses the frames read ahead, on up to `threads` threads
+///
+/// The threads are started with the first batch of several frames
+/// and kept until the decoder is dropped.
+pub struct Workers {
+    threads: usize,
+    #[cfg(feature = "std")]
+    pool: Option<Pool>,
+}
+
+impl Workers {
+    pub fn new(threads: usize) -> Self {
+        Self {
+            threads,
+            #[cfg(feature = "std")]
+            pool: None,
+        }
+    }
+
+    /// Decompresses `jobs`, returned in the same order with their
+    /// outputs
+    ///
+    /// A single frame is decompressed on the calling thread with
+    /// `tables`.
+    pub fn decode(
+        &mut self,
+        jobs: Vec<ImageJob>,
+        mode: DecodeMode,
+        tables: &mut LzwTables,
+    ) -> Vec<(ImageJob, JobOutput)> {
+        #[cfg(feature = "std")]
+        if self.threads > 1 && jobs.len() > 1 {
+            let threads = self.threads;
+            return self
+                .pool
+                .get_or_insert_with(|| Pool::new(threads))
+                .decode(jobs, mode);
+        }
+        // Without threads, every frame is decoded on this thread
+        #[cfg(not(feature = "std"))]
+        let _ = self.threads;
+
+        jobs.into_iter()
+            .map(|job| {
+                let output = job.decode(mode, tables);
+                (job, output)
+            })
+            .collect()
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+impl Workers {
+    /// Identifiers of the threads started so far
+    pub fn thread_ids(&self) -> Vec<thread::ThreadId> {
+        self.pool
+            .iter()
+            .flat_map(|pool| &pool.threads)
+            .map(|thread| thread.thread().id())
+            .collect()
+    }
+}
+
+/// A job sent to the threads, with its position in the batch
+#[cfg(feature = "std")]
+type Queued = (usize, ImageJob, DecodeMode);
+
+/// A job decompressed by a thread, or the panic that stopped it
+#[cfg(feature = "std")]
+type Done = (usize, ImageJob, thread::Result<JobOutput>);
+
+#[cfg(feature = "std")]
+struct Pool {
+    // Dropped first, which stops the threads
+    queue: Option<mpsc::Sender<Queued>>,
+    // In a mutex to keep the decoder `Sync`
+    done: Mutex<mpsc::Receiver<Done>>,
+    threads: Vec<thread::JoinHandle<()>>,
+}
+
+#[cfg(feature = "std")]
+impl Pool {
+    fn new(threads: usize) -> Self {
+        let (queue, jobs) = mpsc::channel::<Queued>();
+        let (finished, done) = mpsc::channel();
+        let jobs = Arc::new(Mutex::new(jobs));
+
+        let threads = (0..threads)
+            .map(|_| {
+                let jobs = Arc::clone(&jobs);
+                let finished = finished.clone();
+                thread::spawn(move || {
+                    let mut tables = Box::<LzwTables>::default();
+                    // Frames are handed out one at a time, their
+                    // sizes can vary a lot
+                    while let Ok(Ok((i, job, mode))) =
+                        jobs.lock().map(|jobs| jobs.recv())
+                    {
+                        let output = panic::catch_unwind(
+                            AssertUnwindSafe(|| {
+                                job.decode(mode, &mut tables)
+                            }),
+                        );
+                        if finished.send((i, job, output)).is_err() {
+                            break;
+                        }
+                    }
+                })
+            })
+            .collect();
+
+        Self {
+            queue: Some(queue),
+            done: Mutex::new(done),
+            threads,
+        }
+    }
+
+    fn decode(
+        &mut self,
+        jobs: Vec<ImageJob>,
+        mode: DecodeMode,
+    ) -> Vec<(ImageJob, JobOutput)> {
+        let queue = self.queue.as_ref().expect("open until dropped");
+        let count = jobs.len();
+        for (i, job) in jobs.into_iter().enumerate() {
+            queue.send((i, job, mode)).expect("threads are running");
+        }
+
+        // Every job is received before a panic is resumed, so that
+        // none is left for the next batch
+        let done =
+            self.done.get_mut().unwrap_or_else(|e| e.into_inner());
+        let mut outputs: Vec<Option<(ImageJob, JobOutput)>> =
+            (0..count).map(|_| None).collect();
+        let mut panicked = None;
+        for _ in 0..count {
+            let (i, job, output) =
+                done.recv().expect("threads are running");
+            match output {
+                Ok(output) => outputs[i] = Some((job, output)),
+                Err(payload) => panicked = Some(payload),
+            }
+        }
+        if let Some(payload) = panicked {
+            panic::resume_unwind(payload);
+        }
+
+        outputs
+            .into_iter()
+            .map(|output| output.expect("every job is decoded"))
+            .collect()
+    }
+}
+
+#[cfg(feature = "std")]
+impl Drop for Pool {
+    fn drop(&mut self) {
+        self.queue = None;
+        for thread in self.threads.drain(..) {
+            let _ = thread.join();
+        }
+    }
+}
@@ -0,0 +1,464 @@
+use core::fmt::Debug;
+
+use crate::{
+    error::{DecodingError, ErrorKind},
+    structs::{Color, Palette},
+};
+
+pub use crate::render::GifColor;
+
+/// RGBA with straight alpha, the default output format
+pub type Rgba8 = GifColor;
+
+/// Output pixel format of the decoded frames
+///
+/// Palette entries are converted once per frame, the indices are then
+/// expanded with a lookup table.
+pub trait Pixel:
+    Copy + Debug + PartialEq + Send + Sync + 'static
+{
+    /// Converts an opaque palette color
+    fn from_color(color: Color) -> Self;
+
+    /// The value of the transparent index and of the background
+    fn transparent() -> Self;
+
+    /// Converts a color with straight alpha, as produced by the box
+    /// filter of scaled decoding
+    fn from_rgba(color: GifColor) -> Self;
+
+    /// Returns the color with straight alpha, formats without alpha
+    /// are opaque
+    fn to_rgba(self) -> GifColor;
+
+    /// Number of bytes written by `write_bytes`
+    const SIZE: usize;
+
+    /// Writes the pixel to the first `SIZE` bytes of `out`
+    fn write_bytes(self, out: &mut [u8]);
+}
+
+impl Pixel for GifColor {
+    fn from_color(color: Color) -> Self {
+        GifColor::opaque(color.r, color.g, color.b)
+    }
+
+    fn transparent() -> Self {
+        GifColor::transparent()
+    }
+
+    fn from_rgba(color: GifColor) -> Self {
+        color
+    }
+
+    fn to_rgba(self) -> GifColor {
+        self
+    }
+
+    const SIZE: usize = 4;
+
+    fn write_bytes(self, out: &mut [u8]) {
+        out[..4].copy_from_slice(&[self.r, self.g, self.b, self.a]);
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Bgra8 {
+    pub b: u8,
+    pub g: u8,
+    pub r: u8,
+    pub a: u8,
+}
+
+impl Pixel for Bgra8 {
+    fn from_color(color: Color) -> Self {
+        Self {
+            b: color.b,
+            g: color.g,
+            r: color.r,
+            a: 255,
+        }
+    }
+
+    fn transparent() -> Self {
+        Self {
+            b: 0,
+            g: 0,
+            r: 0,
+            a: 0,
+        }
+    }
+
+    fn from_rgba(color: GifColor) -> Self {
+        Self {
+            b: color.b,
+            g: color.g,
+            r: color.r,
+            a: color.a,
+        }
+    }
+
+    fn to_rgba(self) -> GifColor {
+        GifColor::new(self.r, self.g, self.b, self.a)
+    }
+
+    const SIZE: usize = 4;
+
+    fn write_bytes(self, out: &mut [u8]) {
+        out[..4].copy_from_slice(&[self.b, self.g, self.r, self.a]);
+    }
+}
+
+/// RGB without alpha: transparent pixels are black
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rgb8 {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+}
+
+impl Pixel for Rgb8 {
+    fn from_color(color: Color) -> Self {
+        Self {
+            r: color.r,
+            g: color.g,
+            b: color.b,
+        }
+    }
+
+    fn transparent() -> Self {
+        Self { r: 0, g: 0, b: 0 }
+    }
+
+    /// The color is composited over black
+    fn from_rgba(color: GifColor) -> Self {
+        Self {
+            r: multiply(color.r, color.a),
+            g: multiply(color.g, color.a),
+            b: multiply(color.b, color.a),
+        }
+    }
+
+    fn to_rgba(self) -> GifColor {
+        GifColor::opaque(self.r, self.g, self.b)
+    }
+
+    const SIZE: usize = 3;
+
+    fn write_bytes(self, out: &mut [u8]) {
+        out[..3].copy_from_slice(&[self.r, self.g, self.b]);
+    }
+}
+
+/// RGBA with the color channels multiplied by alpha
+///
+/// GIF pixels are either opaque or fully transparent, so this only
+/// differs from `Rgba8` by clearing the color of transparent pixels,
+/// which `Rgba8` does as well.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PremultipliedRgba8 {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+    pub a: u8,
+}
+
+impl Pixel for PremultipliedRgba8 {
+    fn from_color(color: Color) -> Self {
+        Self {
+            r: color.r,
+            g: color.g,
+            b: color.b,
+            a: 255,
+        }
+    }
+
+    fn transparent() -> Self {
+        Self {
+            r: 0,
+            g: 0,
+            b: 0,
+            a: 0,
+        }
+    }
+
+    fn from_rgba(color: GifColor) -> Self {
+        Self {
+            r: multiply(color.r, color.a),
+            g: multiply(color.g, color.a),
+            b: multiply(color.b, color.a),
+            a: color.a,
+        }
+    }
+
+    fn to_rgba(self) -> GifColor {
+        if self.a == 0 {
+            return GifColor::transparent();
+        }
+
+        let divide = |c: u8| {
+            ((c as u32 * 255 + self.a as u32 / 2) / self.a as u32).min(255)
+                as u8
+        };
+        GifColor::new(divide(self.r), divide(self.g), divide(self.b), self.a)
+    }
+
+    const SIZE: usize = 4;
+
+    fn write_bytes(self, out: &mut [u8]) {
+        out[..4].copy_from_slice(&[self.r, self.g, self.b, self.a]);
+    }
+}
+
+/// ARGB packed in a native endian `u32`, alpha in the high byte
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Argb32(pub u32);
+
+impl Pixel for Argb32 {
+    fn from_color(color: Color) -> Self {
+        Self(
+            0xFF00_0000
+                | (color.r as u32) << 16
+                | (color.g as u32) << 8
+                | color.b as u32,
+        )
+    }
+
+    fn transparent() -> Self {
+        Self(0)
+    }
+
+    fn from_rgba(color: GifColor) -> Self {
+        Self(
+            (color.a as u32) << 24
+                | (color.r as u32) << 16
+                | (color.g as u32) << 8
+                | color.b as u32,
+        )
+    }
+
+    fn to_rgba(self) -> GifColor {
+        let [a, r, g, b] = self.0.to_be_bytes();
+        GifColor::new(r, g, b, a)
+    }
+
+    const SIZE: usize = 4;
+
+    fn write_bytes(self, out: &mut [u8]) {
+        out[..4].copy_from_slice(&self.0.to_ne_bytes());
+    }
+}
+
+/// Luma with the Rec. 601 weights: transparent pixels are black
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Gray8(pub u8);
+
+impl Pixel for Gray8 {
+    fn from_color(color: Color) -> Self {
+        let luma = 77 * color.r as u32
+            + 150 * color.g as u32
+            + 29 * color.b as u32;
+        Self(((luma + 128) >> 8) as u8)
+    }
+
+    fn transparent() -> Self {
+        Self(0)
+    }
+
+    /// The color is composited over black
+    fn from_rgba(color: GifColor) -> Self {
+        let Gray8(luma) =
+            Self::from_color(Color { r: color.r, g: color.g, b: color.b });
+        Self(multiply(luma, color.a))
+    }
+
+    fn to_rgba(self) -> GifColor {
+        GifColor::opaque(self.0, self.0, self.0)
+    }
+
+    const SIZE: usize = 1;
+
+    fn write_bytes(self, out: &mut [u8]) {
+        out[0] = self.0;
+    }
+}
+
+/// Returns `c * a / 255`, rounded
+fn multiply(c: u8, a: u8) -> u8 {
+    let v = c as u32 * a as u32 + 128;
+    ((v + (v >> 8)) >> 8) as u8
+}
+
+/// Converts a palette to `P`, indices outside of it are black
+pub(crate) fn lookup_table<P: Pixel>(
+    palette: &Palette,
+    transparent_index: Option<u8>,
+) -> [P; 256] {
+    let mut table = [P::from_color(Color::default()); 256];
+
+    for (entry, &color) in table.iter_mut().zip(palette) {
+        *entry = P::from_color(color);
+    }
+
+    if let Some(index) = transparent_index {
+        table[index as usize] = P::transparent();
+    }
+
+    table
+}
+
+/// Position of a frame or a canvas in a byte buffer
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BufferLayout {
+    /// Byte offset of the first pixel
+    pub offset: usize,
+    /// Bytes from the start of a row to the start of the next one
+    pub stride: usize,
+}
+
+impl BufferLayout {
+    pub fn new(offset: usize, stride: usize) -> Self {
+        Self { offset, stride }
+    }
+
+    /// Rows stored one after the other, starting at offset 0
+    pub fn packed<P: Pixel>(width: u16) -> Self {
+        Self::new(0, width as usize * P::SIZE)
+    }
+
+    /// Checks that `width * height` pixels of `P` fit in a buffer of
+    /// `len` bytes
+    pub(crate) fn check<P: Pixel>(
+        &self,
+        width: usize,
+        height: usize,
+        len: usize,
+    ) -> Result<(), DecodingError> {
+        // A layout beyond the address space fits in no buffer
+        let overflow = || {
+            DecodingError::from(ErrorKind::InvalidBuffer {
+                required: usize::MAX,
+                len,
+            })
+        };
+
+        let row_bytes =
+            width.checked_mul(P::SIZE).ok_or_else(overflow)?;
+        if height > 0 && self.stride < row_bytes {
+            return Err(ErrorKind::InvalidStride {
+                stride: self.stride,
+                row_bytes,
+            }
+            .into());
+        }
+
+        let required = match height {
+            0 => 0,
+            _ => (height - 1)
+                .checked_mul(self.stride)
+                .and_then(|rows| rows.checked_add(self.offset))
+                .and_then(|start| start.checked_add(row_bytes))
+                .ok_or_else(overflow)?,
+        };
+        if required > len {
+            return Err(ErrorKind::InvalidBuffer { required, len }.into());
+        }
+
+        Ok(())
+    }
+
+    /// Returns the bytes of the first `width` pixels of row `y`
+    pub(crate) fn row<'a, P: Pixel>(
+        &self,
+        buffer: &'a mut [u8],
+        y: usize,
+        width: usize,
+    ) -> &'a mut [u8] {
+        let start = self.offset + y * self.stride;
+        &mut buffer[start..start + width * P::SIZE]
+    }
+}
+
+/// Writes a row of pixels as bytes
+pub(crate) fn write_row<P: Pixel>(pixels: &[P], out: &mut [u8]) {
+    for (pixel, bytes) in pixels.iter().zip(out.chunks_exact_mut(P::SIZE))
+    {
+        pixel.write_bytes(bytes);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::{vec, vec::Vec};
+
+    use super::*;
+
+    /// Bytes of an opaque color, of a half transparent one and of the
+    /// transparent pixel
+    fn bytes<P: Pixel>() -> [Vec<u8>; 3] {
+        let pixels = [
+            P::from_color(Color { r: 200, g: 100, b: 50 }),
+            P::from_rgba(GifColor::new(200, 100, 50, 128)),
+            P::transparent(),
+        ];
+        pixels.map(|pixel| {
+            let mut out = vec![0xAA; P::SIZE];
+            pixel.write_bytes(&mut out);
+            out
+        })
+    }
+
+    #[test]
+    fn byte_order() {
+        let rgba = [200, 100, 50, 255];
+        assert_eq!(
+            bytes::<Rgba8>(),
+            [rgba.to_vec(), vec![200, 100, 50, 128], vec![0; 4]]
+        );
+        assert_eq!(
+            bytes::<Bgra8>(),
+            [
+                vec![50, 100, 200, 255],
+                vec![50, 100, 200, 128],
+                vec![0; 4]
+            ]
+        );
+        assert_eq!(
+            bytes::<Rgb8>(),
+            [vec![200, 100, 50], vec![100, 50, 25], vec![0; 3]]
+        );
+        assert_eq!(
+            bytes::<PremultipliedRgba8>(),
+            [rgba.to_vec(), vec![100, 50, 25, 128], vec![0; 4]]
+        );
+        assert_eq!(
+            bytes::<Argb32>(),
+            [
+                0xFFC8_6432_u32.to_ne_bytes().to_vec(),
+                0x80C8_6432_u32.to_ne_bytes().to_vec(),
+                vec![0; 4]
+            ]
+        );
+        assert_eq!(bytes::<Gray8>(), [vec![124], vec![62], vec![0]]);
+    }
+
+    #[test]
+    fn layout_overflow() {
+        let layouts = [
+            (BufferLayout::new(usize::MAX, 4), 1, 1),
+            (BufferLayout::new(0, usize::MAX), 1, 3),
+            (BufferLayout::new(0, 4), usize::MAX / 2, 1),
+        ];
+        for (layout, width, height) in layouts {
+            let result = layout.check::<GifColor>(width, height, 64);
+            let error = result.unwrap_err();
+            assert_eq!(
+                error.kind(),
+                ErrorKind::InvalidBuffer {
+                    required: usize::MAX,
+                    len: 64
+                }
+            );
+        }
+    }
+}
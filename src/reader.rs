@@ -1,4 +1,7 @@
-use std::io::{self, Read};
+#[cfg(feature = "std")]
+use std::io::{Seek, SeekFrom};
+
+use crate::io::{self, BufRead, Read};
 
 pub struct SubBlockReader<'a, R> {
     reader: &'a mut R,
@@ -33,7 +36,7 @@ impl<'a, R: Read> SubBlockReader<'a, R> {
 }
 
 impl<'a, R: Read> Read for SubBlockReader<'a, R> {
-    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
         // If we find the terminator (0x00), we are finished
         if self.finished {
             return Ok(0);
@@ -67,7 +70,7 @@ impl<'a, R: Read> Read for SubBlockReader<'a, R> {
         }
 
         let max_read =
-            std::cmp::min(buf.len(), self.remaining_in_block);
+            core::cmp::min(buf.len(), self.remaining_in_block);
 
         let read_amount = self.reader.read(&mut buf[..max_read])?;
 
@@ -85,3 +88,258 @@ impl<'a, R: Read> Read for SubBlockReader<'a, R> {
         Ok(read_amount)
     }
 }
+
+/// `BufRead` over a `SubBlockReader`, buffering one sub-block at a
+/// time in place of a heap allocated `BufReader`
+pub(crate) struct SubBlockBufReader<'a, R> {
+    inner: SubBlockReader<'a, R>,
+    block: [u8; 255],
+    pos: usize,
+    len: usize,
+}
+
+impl<'a, R: Read> SubBlockBufReader<'a, R> {
+    pub fn new(reader: &'a mut R) -> Self {
+        Self {
+            inner: SubBlockReader::new(reader),
+            block: [0; 255],
+            pos: 0,
+            len: 0,
+        }
+    }
+
+    /// See `SubBlockReader::consume_to_end`
+    pub fn consume_to_end(&mut self) -> io::Result<()> {
+        self.pos = self.len;
+        self.inner.consume_to_end()
+    }
+}
+
+impl<R: Read> Read for SubBlockBufReader<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let content = self.fill_buf()?;
+        let n = content.len().min(buf.len());
+        buf[..n].copy_from_slice(&content[..n]);
+        self.consume(n);
+        Ok(n)
+    }
+}
+
+impl<R: Read> BufRead for SubBlockBufReader<'_, R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        if self.pos == self.len {
+            // At most the rest of the current sub-block
+            self.len = self.inner.read(&mut self.block)?;
+            self.pos = 0;
+        }
+        Ok(&self.block[self.pos..self.len])
+    }
+
+    fn consume(&mut self, amount: usize) {
+        self.pos = (self.pos + amount).min(self.len);
+    }
+}
+
+/// Data sub-blocks borrowed from a GIF held in memory, see
+/// `Decoder::image_data`
+///
+/// Iterating yields the content of each sub-block in place. Reading
+/// them with `Read` or `BufRead` concatenates their content.
+/// Missing data is handled as by `Decoder::lzw_reader`: the sub-blocks
+/// end where the input ends before a length byte, and a sub-block cut
+/// short is an error once its bytes have been returned.
+#[derive(Debug, Clone)]
+pub struct SubBlocks<'a> {
+    data: &'a [u8],
+    // Offset of the next byte, `data[pos..block_end]` is the unread
+    // content of the current sub-block
+    pos: usize,
+    block_end: usize,
+    // Where the current sub-block should end, beyond `data` if it is
+    // truncated
+    declared_end: usize,
+    finished: bool,
+    terminated: bool,
+    // The truncation error has been returned by the iterator
+    failed: bool,
+}
+
+impl<'a> SubBlocks<'a> {
+    /// `data` starts at the length byte of the first sub-block
+    pub(crate) fn new(data: &'a [u8]) -> Self {
+        Self {
+            data,
+            pos: 0,
+            block_end: 0,
+            declared_end: 0,
+            finished: false,
+            terminated: false,
+            failed: false,
+        }
+    }
+
+    /// Bytes of the input consumed so far, length bytes and terminator
+    /// included
+    pub fn position(&self) -> usize {
+        self.pos
+    }
+
+    /// Returns true once the block terminator has been read
+    pub fn is_terminated(&self) -> bool {
+        self.terminated
+    }
+
+    /// Consumes every remaining sub-block, see
+    /// `SubBlockReader::consume_to_end`
+    pub fn consume_to_end(&mut self) -> io::Result<()> {
+        loop {
+            let n = self.next_content()?.len();
+            if n == 0 {
+                return Ok(());
+            }
+            self.consume(n);
+        }
+    }
+
+    /// Returns the unread content of the current sub-block, moving to
+    /// the next one if it is exhausted. Empty at the end of the data.
+    #[inline(always)]
+    fn next_content(&mut self) -> io::Result<&'a [u8]> {
+        if self.pos < self.block_end {
+            return Ok(&self.data[self.pos..self.block_end]);
+        }
+        self.next_block()
+    }
+
+    /// Moves to the next sub-block, see `next_content`
+    fn next_block(&mut self) -> io::Result<&'a [u8]> {
+        loop {
+            if self.pos < self.block_end {
+                return Ok(&self.data[self.pos..self.block_end]);
+            }
+            if self.finished {
+                return Ok(&[]);
+            }
+            if self.block_end < self.declared_end {
+                return Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    "GIF stream truncated inside a data sub-block",
+                ));
+            }
+
+            match self.data.get(self.pos) {
+                // Tolerated as for `SubBlockReader`
+                None => self.finished = true,
+                Some(0) => {
+                    self.pos += 1;
+                    self.finished = true;
+                    self.terminated = true;
+                }
+                Some(&len) => {
+                    self.pos += 1;
+                    self.declared_end = self.pos + len as usize;
+                    self.block_end =
+                        self.declared_end.min(self.data.len());
+                }
+            }
+        }
+    }
+}
+
+impl<'a> Iterator for SubBlocks<'a> {
+    type Item = io::Result<&'a [u8]>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed {
+            return None;
+        }
+
+        match self.next_content() {
+            Ok([]) => None,
+            Ok(content) => {
+                self.pos = self.block_end;
+                Some(Ok(content))
+            }
+            Err(e) => {
+                self.failed = true;
+                Some(Err(e))
+            }
+        }
+    }
+}
+
+impl Read for SubBlocks<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let content = self.next_content()?;
+        let n = content.len().min(buf.len());
+        buf[..n].copy_from_slice(&content[..n]);
+        self.pos += n;
+        Ok(n)
+    }
+}
+
+impl BufRead for SubBlocks<'_> {
+    #[inline(always)]
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        self.next_content()
+    }
+
+    #[inline(always)]
+    fn consume(&mut self, amount: usize) {
+        self.pos = (self.pos + amount).min(self.block_end);
+    }
+}
+
+/// Keeps track of the number of bytes read since the start of the GIF
+pub struct PositionReader<R> {
+    inner: R,
+    position: u64,
+}
+
+impl<R> PositionReader<R> {
+    pub fn new(inner: R) -> Self {
+        Self { inner, position: 0 }
+    }
+
+    pub fn position(&self) -> u64 {
+        self.position
+    }
+
+    pub fn get_ref(&self) -> &R {
+        &self.inner
+    }
+
+    pub fn get_mut(&mut self) -> &mut R {
+        &mut self.inner
+    }
+
+    /// Consumes `n` bytes of the inner reader with `advance`, without
+    /// reading them
+    pub fn consume_with(
+        &mut self,
+        n: usize,
+        advance: fn(&mut R, usize),
+    ) {
+        advance(&mut self.inner, n);
+        self.position += n as u64;
+    }
+}
+
+impl<R: Read> Read for PositionReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.position += n as u64;
+        Ok(n)
+    }
+}
+
+#[cfg(feature = "std")]
+impl<R: Seek> PositionReader<R> {
+    /// Moves to `position` bytes from the start of the GIF
+    pub fn seek_to(&mut self, position: u64) -> io::Result<()> {
+        let delta = position as i64 - self.position as i64;
+        self.inner.seek(SeekFrom::Current(delta))?;
+        self.position = position;
+        Ok(())
+    }
+}
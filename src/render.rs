@@ -1,6 +1,11 @@
+use alloc::vec;
+
 use crate::{
-    error::DecodingError,
-    structs::{Color, GraphicControl, Palette},
+    font,
+    interlace::RowOrder,
+    options::{IncompleteFill, Region},
+    pixel::{self, Pixel},
+    structs::{Palette, PlainText},
 };
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -23,30 +28,135 @@ impl GifColor {
     pub const fn transparent() -> Self {
         Self::new(0, 0, 0, 0)
     }
+}
 
-    pub fn map_indices_to_rgba(
-        index_buffer: &[u8],
-        palette: &Palette,
-        graphic_control_extension: &Option<GraphicControl>,
-        rgba_buffer: &mut [GifColor],
-    ) -> Result<(), DecodingError> {
-        let transparent_index = graphic_control_extension.as_ref().and_then(|g| g.transparent_color_index);
+/// Expands color indices to pixels
+pub fn map_indices<P: Pixel>(
+    index_buffer: &[u8],
+    palette: &Palette,
+    transparent_index: Option<u8>,
+    pixels: &mut [P],
+) {
+    let table = pixel::lookup_table::<P>(palette, transparent_index);
 
-        for (i, &index) in index_buffer.iter().enumerate() {
-            let alpha = match transparent_index {
-                Some(t_index) if index == t_index => 0,
-                _ => 255,
-            };
+    for (pixel, &index) in pixels.iter_mut().zip(index_buffer) {
+        *pixel = table[index as usize];
+    }
+}
+
+/// Draws the text grid as color indices, with the built-in font scaled
+/// to the cell size
+/// The buffer must be big `width * height` of the text grid
+pub fn rasterize_plain_text(text: &PlainText, index_buffer: &mut [u8]) {
+    let width = text.width as usize;
+    let cell_width = text.cell_width as usize;
+    let cell_height = text.cell_height as usize;
+    let columns = text.columns();
 
-            let rgb_color = if (index as usize) < palette.len() {
-                palette[index as usize]
-            } else {
-                Color::default()
+    index_buffer.fill(text.bg_color_index);
+
+    if columns == 0 {
+        return;
+    }
+
+    let cells = columns * text.rows();
+    for (cell, &ch) in text.text.iter().take(cells).enumerate() {
+        let cell_x = (cell % columns) * cell_width;
+        let cell_y = (cell / columns) * cell_height;
+
+        for y in 0..cell_height {
+            let glyph_y = y * font::BOX_HEIGHT / cell_height;
+            for x in 0..cell_width {
+                let glyph_x = x * font::BOX_WIDTH / cell_width;
+                if font::pixel(ch, glyph_x, glyph_y) {
+                    let idx = (cell_y + y) * width + cell_x + x;
+                    index_buffer[idx] = text.fg_color_index;
+                }
+            }
+        }
+    }
+}
+
+/// How far the decoding of a frame went, and the part of it kept
+#[derive(Debug, Clone, Copy)]
+pub struct FrameProgress {
+    pub width: usize,
+    pub height: usize,
+    pub interlaced: bool,
+    /// Pixels decoded, in the order they are stored
+    pub decoded: usize,
+    /// Part of the frame kept in the buffers, in frame coordinates
+    pub crop: Region,
+}
+
+impl FrameProgress {
+    pub fn is_complete(&self) -> bool {
+        self.decoded == self.width * self.height
+    }
+}
+
+/// Fills the pixels of an incomplete frame that were not decoded
+/// `pixels` holds the cropped part of the frame, `transparent` is used
+/// by `IncompleteFill::Transparent`.
+pub fn fill_incomplete<T: Copy>(
+    pixels: &mut [T],
+    progress: &FrameProgress,
+    fill: IncompleteFill,
+    transparent: T,
+) {
+    let left = progress.crop.left as usize;
+    let top = progress.crop.top as usize;
+    let width = progress.crop.width as usize;
+    let height = progress.crop.height as usize;
+
+    incomplete_rows(progress, |y, done, src| {
+        if y < top || y >= top + height {
+            return;
+        }
+
+        for x in done.max(left)..left + width {
+            pixels[(y - top) * width + x - left] = match (fill, src) {
+                // Rows above the crop are not kept
+                (IncompleteFill::LastRow, Some(src)) if src >= top => {
+                    pixels[(src - top) * width + x - left]
+                }
+                _ => transparent,
             };
+        }
+    });
+}
+
+/// Calls `f(y, done, source)` for each row of an incomplete frame that
+/// was not entirely decoded, with `done` the pixels decoded in the row
+/// and `source` the last complete row above it
+pub fn incomplete_rows(
+    progress: &FrameProgress,
+    mut f: impl FnMut(usize, usize, Option<usize>),
+) {
+    let width = progress.width;
+    if width == 0 {
+        return;
+    }
+
+    // Pixels decoded in each destination row
+    let mut row_pixels = vec![0; progress.height];
+    let mut remaining = progress.decoded;
+    for y in RowOrder::new(progress.height, progress.interlaced) {
+        let n = remaining.min(width);
+        row_pixels[y] = n;
+        remaining -= n;
+        if remaining == 0 {
+            break;
+        }
+    }
 
-            rgba_buffer[i] = GifColor::new(rgb_color.r, rgb_color.g, rgb_color.b, alpha);
+    let mut last_complete_row = None;
+    for (y, &done) in row_pixels.iter().enumerate() {
+        if done == width {
+            last_complete_row = Some(y);
+            continue;
         }
 
-        Ok(())
+        f(y, done, last_complete_row);
     }
 }
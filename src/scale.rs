@@ -0,0 +1,306 @@
+use alloc::{vec, vec::Vec};
+
+use crate::{
+    decoder::RowTarget,
+    options::{Region, ScaleFilter},
+    pixel::GifColor,
+    structs::{Color, Palette},
+};
+
+/// Grid of the scaled pixels: each one covers a block of `factor` by
+/// `factor` screen pixels, starting from the origin of the bounds
+#[derive(Debug, Clone, Copy)]
+pub struct ScaleGrid {
+    factor: usize,
+    filter: ScaleFilter,
+    left: usize,
+    top: usize,
+    right: usize,
+    bottom: usize,
+}
+
+impl ScaleGrid {
+    /// Screen pixels outside of `bounds` are never drawn
+    pub fn new(
+        factor: usize,
+        filter: ScaleFilter,
+        bounds: Region,
+    ) -> Self {
+        Self {
+            factor,
+            filter,
+            left: bounds.left as usize,
+            top: bounds.top as usize,
+            right: bounds.left as usize + bounds.width as usize,
+            bottom: bounds.top as usize + bounds.height as usize,
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        (self.right - self.left).div_ceil(self.factor)
+    }
+
+    pub fn height(&self) -> usize {
+        (self.bottom - self.top).div_ceil(self.factor)
+    }
+
+    /// Returns the scaled pixels covering a rectangle of the screen, as
+    /// the grid columns and rows `(x0, y0, x1, y1)`, end excluded
+    pub fn cover(
+        &self,
+        rect: Region,
+    ) -> (usize, usize, usize, usize) {
+        let clip =
+            |start: usize, end: usize, min: usize, max: usize| {
+                let start = start.clamp(min, max) - min;
+                let end = end.clamp(min, max) - min;
+                if start >= end {
+                    (0, 0)
+                } else {
+                    (start / self.factor, end.div_ceil(self.factor))
+                }
+            };
+
+        let (x0, x1) = clip(
+            rect.left as usize,
+            rect.left as usize + rect.width as usize,
+            self.left,
+            self.right,
+        );
+        let (y0, y1) = clip(
+            rect.top as usize,
+            rect.top as usize + rect.height as usize,
+            self.top,
+            self.bottom,
+        );
+
+        if x0 == x1 || y0 == y1 {
+            (0, 0, 0, 0)
+        } else {
+            (x0, y0, x1, y1)
+        }
+    }
+
+    /// Returns the scaled pixel a screen pixel contributes to, if any
+    pub fn cell(&self, x: usize, y: usize) -> Option<(usize, usize)> {
+        if x < self.left
+            || x >= self.right
+            || y < self.top
+            || y >= self.bottom
+        {
+            return None;
+        }
+
+        let (dx, dy) = (x - self.left, y - self.top);
+        if self.filter == ScaleFilter::Point
+            && !(dx.is_multiple_of(self.factor)
+                && dy.is_multiple_of(self.factor))
+        {
+            return None;
+        }
+
+        Some((dx / self.factor, dy / self.factor))
+    }
+
+    /// Returns true if the screen row `y` contributes to the grid
+    pub fn row_used(&self, y: usize) -> bool {
+        y >= self.top
+            && y < self.bottom
+            && (self.filter == ScaleFilter::Box
+                || (y - self.top).is_multiple_of(self.factor))
+    }
+
+    /// Returns how many screen pixels of the cell contribute to it and
+    /// how many of them are inside `rect`
+    pub fn coverage(
+        &self,
+        cx: usize,
+        cy: usize,
+        rect: Region,
+    ) -> (usize, usize) {
+        let x0 = self.left + cx * self.factor;
+        let y0 = self.top + cy * self.factor;
+        let (x1, y1) = match self.filter {
+            ScaleFilter::Box => (
+                (x0 + self.factor).min(self.right),
+                (y0 + self.factor).min(self.bottom),
+            ),
+            ScaleFilter::Point => (x0 + 1, y0 + 1),
+        };
+
+        let overlap =
+            |start: usize, end: usize, from: u16, len: u16| {
+                let from = from as usize;
+                end.min(from + len as usize)
+                    .saturating_sub(start.max(from))
+            };
+
+        let area = (x1 - x0) * (y1 - y0);
+        let inside = overlap(x0, x1, rect.left, rect.width)
+            * overlap(y0, y1, rect.top, rect.height);
+        (area, inside)
+    }
+}
+
+/// A frame being scaled: sums of the opaque colors and count of the
+/// opaque pixels of each scaled pixel
+#[derive(Debug, Clone)]
+pub struct ScaledFrame {
+    /// Position and size on the grid
+    pub left: usize,
+    pub top: usize,
+    pub width: usize,
+    pub height: usize,
+    sums: Vec<[u16; 4]>,
+}
+
+impl ScaledFrame {
+    /// `rect` is the frame on the screen
+    pub fn new(grid: &ScaleGrid, rect: Region) -> Self {
+        let (x0, y0, x1, y1) = grid.cover(rect);
+        let (width, height) = (x1 - x0, y1 - y0);
+
+        Self {
+            left: x0,
+            top: y0,
+            width,
+            height,
+            sums: vec![[0; 4]; width * height],
+        }
+    }
+
+    /// Returns the bytes allocated for a frame covering `rect`
+    pub fn allocation(grid: &ScaleGrid, rect: Region) -> u64 {
+        let (x0, y0, x1, y1) = grid.cover(rect);
+        ((x1 - x0) * (y1 - y0) * 8) as u64
+    }
+
+    /// Adds an opaque screen pixel
+    pub fn add(
+        &mut self,
+        grid: &ScaleGrid,
+        x: usize,
+        y: usize,
+        c: Color,
+    ) {
+        let Some((cx, cy)) = grid.cell(x, y) else {
+            return;
+        };
+
+        let sum = &mut self.sums
+            [(cy - self.top) * self.width + cx - self.left];
+        sum[0] += c.r as u16;
+        sum[1] += c.g as u16;
+        sum[2] += c.b as u16;
+        sum[3] += 1;
+    }
+
+    /// Returns the scaled pixel `i` with straight alpha
+    /// Pixels of `rect` missing from the sums are transparent.
+    pub fn pixel(
+        &self,
+        grid: &ScaleGrid,
+        rect: Region,
+        i: usize,
+    ) -> GifColor {
+        let [r, g, b, count] = self.sums[i];
+        if count == 0 {
+            return GifColor::transparent();
+        }
+
+        let cx = self.left + i % self.width;
+        let cy = self.top + i / self.width;
+        let (area, _) = grid.coverage(cx, cy, rect);
+
+        let count = count as u32;
+        let average =
+            |sum: u16| ((sum as u32 + count / 2) / count) as u8;
+        let alpha = (count * 255 + area as u32 / 2) / area as u32;
+
+        GifColor::new(average(r), average(g), average(b), alpha as u8)
+    }
+}
+
+/// Colors of the indices, `None` for the transparent index and black
+/// beyond the palette
+pub fn color_table(
+    palette: &Palette,
+    transparent_index: Option<u8>,
+) -> [Option<Color>; 256] {
+    let mut table = [Some(Color::default()); 256];
+
+    for (entry, &color) in table.iter_mut().zip(palette) {
+        *entry = Some(color);
+    }
+
+    if let Some(index) = transparent_index {
+        table[index as usize] = None;
+    }
+
+    table
+}
+
+/// Composites `src` over `dst`, both with straight alpha
+pub fn over(src: GifColor, dst: GifColor) -> GifColor {
+    let sa = src.a as u32;
+    let da = dst.a as u32 * (255 - sa) / 255;
+    let a = sa + da;
+    if a == 0 {
+        return GifColor::transparent();
+    }
+
+    let mix = |s: u8, d: u8| {
+        ((s as u32 * sa + d as u32 * da + a / 2) / a) as u8
+    };
+    GifColor::new(
+        mix(src.r, dst.r),
+        mix(src.g, dst.g),
+        mix(src.b, dst.b),
+        a as u8,
+    )
+}
+
+/// Rows decoded in a single row of indices and added to a
+/// `ScaledFrame`
+pub struct ScaleRows<'a> {
+    pub row: &'a mut [u8],
+    pub table: [Option<Color>; 256],
+    /// Indices from `palette_len` are checked, except the transparent
+    /// one
+    pub palette_len: usize,
+    pub transparent_index: Option<u8>,
+    /// First index beyond the palette
+    pub invalid_index: Option<u8>,
+    pub grid: ScaleGrid,
+    /// Position of the frame on the screen
+    pub left: usize,
+    pub top: usize,
+    pub frame: &'a mut ScaledFrame,
+}
+
+impl RowTarget for ScaleRows<'_> {
+    fn row_buffer(&mut self, _y: usize) -> &mut [u8] {
+        self.row
+    }
+
+    fn row_decoded(&mut self, y: usize, len: usize) {
+        if self.palette_len < 256 && self.invalid_index.is_none() {
+            self.invalid_index =
+                self.row[..len].iter().copied().find(|&i| {
+                    i as usize >= self.palette_len
+                        && Some(i) != self.transparent_index
+                });
+        }
+
+        let y = self.top + y;
+        if !self.grid.row_used(y) {
+            return;
+        }
+
+        for (x, &index) in self.row[..len].iter().enumerate() {
+            if let Some(color) = self.table[index as usize] {
+                self.frame.add(&self.grid, self.left + x, y, color);
+            }
+        }
+    }
+}
@@ -0,0 +1,759 @@
+use alloc::{
+    boxed::Box, collections::VecDeque, sync::Arc, vec, vec::Vec,
+};
+use core::mem;
+
+use crate::{
+    decoder::{self, Block, Decoder},
+    error::{DecodingError, ErrorKind, WarningKind},
+    frame::Frame,
+    io::{self, Read},
+    options::{DecodeMode, DecodeOptions, Limit, Region},
+    interlace::RowOrder,
+    lzw::{LzwDecoder, LzwTables},
+    pixel::GifColor,
+    render::{self, FrameProgress},
+    structs::{GraphicControl, ImageDescriptor, Palette},
+};
+
+/// Progress reported by `StreamingDecoder::feed`
+#[derive(Debug, Clone)]
+pub enum Event {
+    /// The header, the Logical Screen Descriptor and the Global Color
+    /// Table have been read, see `StreamingDecoder::decoder`
+    HeaderReady,
+    /// A new frame starts, its indices are available through
+    /// `StreamingDecoder::partial_frame` as rows get decoded
+    FrameStarted(ImageDescriptor),
+    /// A row of the current frame has been decoded
+    /// The value is the destination row, interlacing already resolved.
+    RowDecoded(usize),
+    FrameComplete(Frame),
+    Trailer,
+}
+
+enum DataState {
+    MinCodeSize,
+    BlockLen,
+    BlockData(usize),
+}
+
+struct FrameState {
+    descriptor: ImageDescriptor,
+    local_palette: Option<Palette>,
+    control: Option<GraphicControl>,
+    indices: Vec<u8>,
+    rows: RowOrder,
+    row: Option<usize>,
+    x: usize,
+    // Pixels decoded so far
+    decoded: usize,
+    // `None` before the LZW Minimum Code Size and once the image data
+    // has ended
+    lzw: Option<LzwDecoder<VecDeque<u8>, Box<LzwTables>>>,
+    data: DataState,
+}
+
+impl FrameState {
+    fn new(
+        descriptor: ImageDescriptor,
+        local_palette: Option<Palette>,
+        control: Option<GraphicControl>,
+    ) -> Self {
+        let width = descriptor.width as usize;
+        let height = descriptor.height as usize;
+
+        let mut rows =
+            RowOrder::new(height, descriptor.is_interlaced());
+        let row = if width > 0 { rows.next() } else { None };
+
+        Self {
+            descriptor,
+            local_palette,
+            control,
+            indices: vec![0u8; width * height],
+            rows,
+            row,
+            x: 0,
+            decoded: 0,
+            lzw: None,
+            data: DataState::MinCodeSize,
+        }
+    }
+
+    fn progress(&self) -> FrameProgress {
+        let descriptor = &self.descriptor;
+        FrameProgress {
+            width: descriptor.width as usize,
+            height: descriptor.height as usize,
+            interlaced: descriptor.is_interlaced(),
+            decoded: self.decoded,
+            crop: Region::new(
+                0,
+                0,
+                descriptor.width,
+                descriptor.height,
+            ),
+        }
+    }
+
+    /// Decodes every pixel available from the compressed data fed so far
+    fn decode_available(
+        &mut self,
+        events: &mut Vec<Event>,
+    ) -> io::Result<()> {
+        let Some(lzw) = &mut self.lzw else {
+            return Ok(());
+        };
+
+        let width = self.descriptor.width as usize;
+
+        while let Some(y) = self.row {
+            let start = y * width + self.x;
+            let n = match lzw
+                .decode_bytes(&mut self.indices[start..y * width + width])
+            {
+                Ok(n) => n,
+                // Wait for the next chunk
+                Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => 0,
+                Err(e) => return Err(e),
+            };
+            if n == 0 {
+                break;
+            }
+
+            self.x += n;
+            self.decoded += n;
+            if self.x == width {
+                events.push(Event::RowDecoded(y));
+                self.x = 0;
+                self.row = self.rows.next();
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Push based decoder: data is fed in chunks as it arrives, decoding
+/// goes as far as the available bytes allow and resumes on the next
+/// call to `feed`
+///
+/// Blocks other than the image data are decoded by a `Decoder` once
+/// they are complete, image data is decoded as soon as it arrives.
+/// The frames follow the decode mode as with `Decoder::next_frame`.
+pub struct StreamingDecoder {
+    options: DecodeOptions,
+    header: Vec<u8>,
+    decoder: Option<Decoder<VecDeque<u8>>>,
+    control: Option<GraphicControl>,
+    frame: Option<FrameState>,
+    scan: BlockScan,
+    finished: bool,
+}
+
+impl Default for StreamingDecoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl StreamingDecoder {
+    pub fn new() -> Self {
+        Self::with_options(DecodeOptions::default())
+    }
+
+    pub fn with_options(options: DecodeOptions) -> Self {
+        Self {
+            options,
+            header: Vec::new(),
+            decoder: None,
+            control: None,
+            frame: None,
+            scan: BlockScan::default(),
+            finished: false,
+        }
+    }
+
+    /// Returns the decoder of the non image blocks, available after
+    /// `Event::HeaderReady`: it gives access to the screen descriptor,
+    /// the palette, and the metadata and warnings found so far
+    pub fn decoder(&self) -> Option<&Decoder<VecDeque<u8>>> {
+        self.decoder.as_ref()
+    }
+
+    pub fn decoder_mut(
+        &mut self,
+    ) -> Option<&mut Decoder<VecDeque<u8>>> {
+        self.decoder.as_mut()
+    }
+
+    /// Returns the frame being decoded and its indices
+    /// Rows not reported by `Event::RowDecoded` yet are 0.
+    pub fn partial_frame(&self) -> Option<(&ImageDescriptor, &[u8])> {
+        self.frame.as_ref().map(|frame| {
+            (&frame.descriptor, frame.indices.as_slice())
+        })
+    }
+
+    /// Returns true once the trailer has been read
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
+
+    /// Feeds the next chunk of the GIF and returns what could be
+    /// decoded with it
+    pub fn feed(
+        &mut self,
+        data: &[u8],
+    ) -> Result<Vec<Event>, DecodingError> {
+        let mut events = Vec::new();
+
+        if self.finished {
+            return Ok(events);
+        }
+
+        match &mut self.decoder {
+            Some(decoder) => {
+                decoder.reader_mut().get_mut().extend(data)
+            }
+            None => {
+                self.header.extend_from_slice(data);
+                if !header_available(&self.header) {
+                    return Ok(events);
+                }
+
+                let input =
+                    VecDeque::from(mem::take(&mut self.header));
+                let mut decoder =
+                    Decoder::with_options(input, self.options.clone())?;
+                decoder.skip_header_records();
+
+                self.decoder = Some(decoder);
+                events.push(Event::HeaderReady);
+            }
+        }
+
+        if let Err(e) = self.process(&mut events) {
+            return Err(match &self.decoder {
+                Some(decoder) => decoder.locate(e),
+                None => e,
+            });
+        }
+
+        Ok(events)
+    }
+
+    fn process(
+        &mut self,
+        events: &mut Vec<Event>,
+    ) -> Result<(), DecodingError> {
+        let Some(decoder) = &mut self.decoder else {
+            return Ok(());
+        };
+
+        loop {
+            if let Some(frame) = &mut self.frame {
+                if !Self::decode_image_data(decoder, frame, events)? {
+                    return Ok(());
+                }
+
+                let frame = self.frame.take().unwrap();
+                let progress = frame.progress();
+                let indexed = decoder.indexed_frame(
+                    &frame.descriptor,
+                    frame.local_palette.map(Arc::new),
+                    frame.control,
+                    frame.indices,
+                )?;
+                let mut decoded = decoder.expand_frame(indexed)?;
+                decoded.complete = progress.is_complete();
+                if !decoded.complete
+                    && decoder.mode() == DecodeMode::Lenient
+                {
+                    render::fill_incomplete(
+                        &mut decoded.pixels,
+                        &progress,
+                        decoder.incomplete_fill(),
+                        GifColor::transparent(),
+                    );
+                }
+                events.push(Event::FrameComplete(decoded));
+                continue;
+            }
+
+            let max_extension_bytes =
+                self.options.limits.max_extension_bytes;
+            if !block_available(
+                decoder.reader_mut().get_ref(),
+                &mut self.scan,
+                max_extension_bytes,
+            )? {
+                return Ok(());
+            }
+
+            self.scan = BlockScan::default();
+            match decoder.next_record()? {
+                Block::GraphicControl(ext) => {
+                    self.control = Some(ext)
+                }
+                Block::PlainText(text) => {
+                    let control = self.control.take();
+                    if decoder.renders_plain_text() {
+                        let frame = decoder
+                            .plain_text_frame(&text, control)?;
+                        let frame = decoder.expand_frame(frame)?;
+                        events.push(Event::FrameComplete(frame));
+                    }
+                }
+                Block::ImageDescriptor(descriptor, local_palette) => {
+                    decoder.image_data_consumed();
+                    decoder.allocate_frame(
+                        descriptor.width,
+                        descriptor.height,
+                        1,
+                    )?;
+                    self.frame = Some(FrameState::new(
+                        descriptor,
+                        local_palette,
+                        self.control.take(),
+                    ));
+                    events.push(Event::FrameStarted(descriptor));
+                }
+                Block::Trailer => {
+                    self.finished = true;
+                    events.push(Event::Trailer);
+                    return Ok(());
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Moves the available image data to the LZW decoder
+    /// Returns true once the block terminator has been read
+    fn decode_image_data(
+        decoder: &mut Decoder<VecDeque<u8>>,
+        frame: &mut FrameState,
+        events: &mut Vec<Event>,
+    ) -> Result<bool, DecodingError> {
+        let mut block = [0u8; 255];
+
+        loop {
+            let input = decoder.reader_mut();
+            if input.get_ref().is_empty() {
+                return Ok(false);
+            }
+
+            match frame.data {
+                DataState::MinCodeSize => {
+                    decoder.begin_image_data();
+                    let input = decoder.reader_mut();
+                    input.read_exact(&mut block[..1])?;
+                    decoder.check_min_code_size(block[0])?;
+                    frame.lzw = Some(LzwDecoder::with_tables(
+                        VecDeque::new(),
+                        block[0],
+                        decoder.take_lzw_tables(),
+                    ));
+                    frame.data = DataState::BlockLen;
+                }
+                DataState::BlockLen => {
+                    input.read_exact(&mut block[..1])?;
+                    match block[0] {
+                        0 => {
+                            Self::end_image_data(
+                                decoder,
+                                frame,
+                                Ok(()),
+                            )?;
+                            return Ok(true);
+                        }
+                        len => {
+                            frame.data =
+                                DataState::BlockData(len as usize)
+                        }
+                    }
+                }
+                DataState::BlockData(remaining) => {
+                    let n = remaining.min(input.get_ref().len());
+                    input.read_exact(&mut block[..n])?;
+
+                    if let Some(lzw) = &mut frame.lzw {
+                        lzw.get_mut().extend(&block[..n]);
+                    }
+
+                    frame.data = match remaining - n {
+                        0 => DataState::BlockLen,
+                        remaining => DataState::BlockData(remaining),
+                    };
+
+                    if let Err(e) = frame.decode_available(events) {
+                        Self::end_image_data(decoder, frame, Err(e))?;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Checks the end of the image data with the rules of the decode
+    /// mode, as `Decoder` does, once the data is complete or the
+    /// decompression failed with `result`
+    fn end_image_data(
+        decoder: &mut Decoder<VecDeque<u8>>,
+        frame: &mut FrameState,
+        result: io::Result<()>,
+    ) -> Result<(), DecodingError> {
+        // Ended early by a recovered error
+        let Some(mut lzw) = frame.lzw.take() else {
+            return Ok(());
+        };
+
+        let result = match result {
+            Ok(()) if frame.row.is_some() && !lzw.is_finished() => {
+                Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    "End of the image data before the last pixel",
+                ))
+            }
+            result => result,
+        };
+        let end = decoder::finish_image_data(
+            &mut lzw,
+            result,
+            frame.decoded,
+            frame.indices.len(),
+            decoder.mode(),
+        );
+        decoder.restore_lzw_tables(lzw.into_tables());
+
+        if end?.1 {
+            decoder.warn(WarningKind::ExcessImageData);
+        }
+        Ok(())
+    }
+}
+
+/// Returns true if the header, the Logical Screen Descriptor and the
+/// Global Color Table are complete
+fn header_available(buf: &[u8]) -> bool {
+    if buf.len() < 13 {
+        return false;
+    }
+
+    let packed = buf[10];
+    let palette_len = if packed & 0b1000_0000 != 0 {
+        3 << ((packed & 0b0000_0111) + 1)
+    } else {
+        0
+    };
+
+    buf.len() >= 13 + palette_len
+}
+
+/// Progress of `block_available` through an extension that is not
+/// complete yet, so that each chunk only scans the new bytes
+#[derive(Default)]
+struct BlockScan {
+    // Offset of the next sub-block length byte, 0 before the
+    // extension has been found
+    next: usize,
+    // Data bytes of the sub-blocks after the first one, which holds
+    // the fixed fields of the known extensions
+    data_len: usize,
+}
+
+/// Returns true if the next block, except for the image data, is
+/// complete and can be read without blocking
+///
+/// An extension larger than `max_extension_bytes` is reported as
+/// soon as its scanned sub-blocks exceed the limit, without waiting
+/// for the rest of it.
+fn block_available(
+    buf: &VecDeque<u8>,
+    scan: &mut BlockScan,
+    max_extension_bytes: Option<usize>,
+) -> Result<bool, DecodingError> {
+    let mut i = 0;
+
+    // Padding
+    while buf.get(i) == Some(&0) {
+        i += 1;
+    }
+
+    match buf.get(i) {
+        None => Ok(false),
+        // Image Descriptor and Local Color Table
+        Some(0x2C) => {
+            let Some(&packed) = buf.get(i + 9) else {
+                return Ok(false);
+            };
+            let palette_len = if packed & 0b1000_0000 != 0 {
+                3 << ((packed & 0b0000_0111) + 1)
+            } else {
+                0
+            };
+            Ok(buf.len() >= i + 10 + palette_len)
+        }
+        // Extension: label and data sub-blocks
+        Some(0x21) => {
+            let first = i + 2;
+            let mut j = scan.next.max(first);
+            loop {
+                let Some(&len) = buf.get(j) else {
+                    scan.next = j;
+                    return Ok(false);
+                };
+                if len == 0 {
+                    return Ok(true);
+                }
+
+                if j != first {
+                    scan.data_len += len as usize;
+                    if max_extension_bytes
+                        .is_some_and(|max| scan.data_len > max)
+                    {
+                        return Err(ErrorKind::LimitExceeded(
+                            Limit::ExtensionBytes,
+                        )
+                        .into());
+                    }
+                }
+                j += len as usize + 1;
+            }
+        }
+        // Trailer, or an invalid introducer reported by `next_record`
+        Some(_) => Ok(true),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_gif;
+
+    /// Feeds `data` in chunks of the sizes returned by `chunk_len`
+    fn stream(
+        data: &[u8],
+        mut chunk_len: impl FnMut() -> usize,
+    ) -> (Vec<Event>, StreamingDecoder) {
+        let mut decoder = StreamingDecoder::new();
+        let mut events = Vec::new();
+        let mut rest = data;
+        while !rest.is_empty() {
+            let len = chunk_len().min(rest.len());
+            let (chunk, next) = rest.split_at(len);
+            events.extend(decoder.feed(chunk).unwrap());
+            rest = next;
+        }
+        assert!(decoder.is_finished());
+        (events, decoder)
+    }
+
+    /// Checks the events against the frames of a `Decoder`
+    fn check_events(data: &[u8], events: &[Event]) {
+        let mut expected = Decoder::new(data).unwrap();
+        let mut events = events.iter();
+        assert!(matches!(events.next(), Some(Event::HeaderReady)));
+
+        while let Some(frame) = expected.next_frame().unwrap() {
+            let Some(Event::FrameStarted(descriptor)) = events.next()
+            else {
+                panic!("missing FrameStarted");
+            };
+            assert_eq!(
+                (descriptor.left, descriptor.top),
+                (frame.left, frame.top)
+            );
+            assert_eq!(
+                (descriptor.width, descriptor.height),
+                (frame.width, frame.height)
+            );
+
+            let mut rows = Vec::new();
+            let decoded = loop {
+                match events.next() {
+                    Some(Event::RowDecoded(y)) => rows.push(*y),
+                    Some(Event::FrameComplete(decoded)) => {
+                        break decoded;
+                    }
+                    event => panic!("unexpected {:?}", event),
+                }
+            };
+            let mut order = RowOrder::new(
+                frame.height as usize,
+                descriptor.is_interlaced(),
+            );
+            assert!(rows.iter().all(|&y| order.next() == Some(y)));
+            assert_eq!(order.next(), None);
+
+            assert_eq!(decoded.pixels, frame.pixels);
+            assert_eq!(decoded.delay_cs, frame.delay_cs);
+            assert_eq!(decoded.disposal, frame.disposal);
+            assert_eq!(
+                decoded.transparent_index,
+                frame.transparent_index
+            );
+            assert!(decoded.complete && frame.complete);
+        }
+
+        assert!(matches!(events.next(), Some(Event::Trailer)));
+        assert!(events.next().is_none());
+    }
+
+    #[test]
+    fn byte_by_byte() {
+        let data = test_gif::animation();
+        let (events, decoder) = stream(&data, || 1);
+        check_events(&data, &events);
+
+        let comments = decoder.decoder().unwrap().comments();
+        assert_eq!(comments.len(), 1);
+    }
+
+    #[test]
+    fn random_chunks() {
+        let data = test_gif::animation();
+        let mut state = 0x9E37_79B9_u32;
+        for max in [2, 7, 64, 300, 5000] {
+            let (events, _) = stream(&data, || {
+                state ^= state << 13;
+                state ^= state >> 17;
+                state ^= state << 5;
+                state as usize % max + 1
+            });
+            check_events(&data, &events);
+        }
+    }
+
+    type Outcome = Result<(Vec<GifColor>, bool), ErrorKind>;
+
+    /// Frames and error of a `Decoder` reading `data`
+    fn decoder_outcomes(
+        data: &[u8],
+        mode: DecodeMode,
+    ) -> Vec<Outcome> {
+        let options = DecodeOptions::new().mode(mode);
+        let mut decoder = match Decoder::with_options(data, options) {
+            Ok(decoder) => decoder,
+            Err(e) => return vec![Err(e.kind())],
+        };
+
+        let mut outcomes = Vec::new();
+        loop {
+            match decoder.next_frame() {
+                Ok(Some(frame)) => {
+                    outcomes.push(Ok((frame.pixels, frame.complete)))
+                }
+                Ok(None) => return outcomes,
+                Err(e) => {
+                    outcomes.push(Err(e.kind()));
+                    return outcomes;
+                }
+            }
+        }
+    }
+
+    /// Frames and error of a `StreamingDecoder` fed `data`, and
+    /// whether it reached the trailer
+    fn stream_outcomes(
+        data: &[u8],
+        mode: DecodeMode,
+    ) -> (Vec<Outcome>, bool) {
+        let options = DecodeOptions::new().mode(mode);
+        let mut decoder = StreamingDecoder::with_options(options);
+        let mut outcomes = Vec::new();
+        for chunk in data.chunks(7) {
+            match decoder.feed(chunk) {
+                Ok(events) => {
+                    for event in events {
+                        if let Event::FrameComplete(f) = event {
+                            outcomes.push(Ok((f.pixels, f.complete)));
+                        }
+                    }
+                }
+                Err(e) => {
+                    outcomes.push(Err(e.kind()));
+                    return (outcomes, true);
+                }
+            }
+        }
+        (outcomes, decoder.is_finished())
+    }
+
+    /// The image data is checked with the rules of the decode mode,
+    /// as `Decoder` does
+    #[test]
+    fn same_results_as_the_decoder() {
+        let data = test_gif::animation();
+        let image = data
+            .windows(3)
+            .position(|w| w == [0x21, 0xF9, 4])
+            .unwrap()
+            + 8;
+        assert_eq!(data[image], 0x2C);
+
+        // Corrupt codes in the first sub-blocks of the first image
+        let mut inputs = Vec::new();
+        let first_block = image + 12..image + 12 + 2 * 255;
+        for at in first_block.filter(|&i| i != image + 12 + 255) {
+            for value in [0, 0xFF] {
+                let mut corrupt = data.clone();
+                corrupt[at] = value;
+                inputs.push(corrupt);
+            }
+        }
+        // Pixels missing or left over
+        for height in [29u16, 31] {
+            let mut resized = data.clone();
+            resized[image + 7..image + 9]
+                .copy_from_slice(&height.to_le_bytes());
+            inputs.push(resized);
+        }
+
+        let modes = [
+            DecodeMode::Standard,
+            DecodeMode::Lenient,
+            DecodeMode::Strict,
+        ];
+        for input in &inputs {
+            for mode in modes {
+                let expected = decoder_outcomes(input, mode);
+                let (outcomes, ended) = stream_outcomes(input, mode);
+                // Without the trailer, the stream waits for more data
+                // where the decoder reports the end of the input
+                let expected = match ended {
+                    true => &expected[..],
+                    false => &expected[..outcomes.len()],
+                };
+                assert_eq!(outcomes, expected, "{:?} mode", mode);
+            }
+        }
+    }
+
+    #[test]
+    fn extension_limit_before_the_end_of_the_extension() {
+        let frame = test_gif::TestFrame::new(0, 0, 4, 4);
+        let mut data = test_gif::gif(4, 4, &[0; 24], &[frame]);
+        let comment = test_gif::sub_blocks(&[b'x'; 100_000], 255);
+        let at = data.len() - 1;
+        data.splice(at..at, [0x21, 0xFE].into_iter().chain(comment));
+
+        let options = DecodeOptions::new().max_extension_bytes(1000);
+        let mut decoder = StreamingDecoder::with_options(options);
+        let mut fed = 0;
+        let error = data
+            .chunks(64)
+            .find_map(|chunk| {
+                fed += chunk.len();
+                decoder.feed(chunk).err()
+            })
+            .unwrap();
+
+        assert_eq!(
+            error.kind(),
+            ErrorKind::LimitExceeded(Limit::ExtensionBytes)
+        );
+        assert!(fed < 2000);
+    }
+}
@@ -1,4 +1,10 @@
-use std::default;
+use alloc::{borrow::Cow, vec::Vec};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Version {
+    Gif87a,
+    Gif89a,
+}
 
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub struct LogicalScreenDescriptor {
@@ -59,6 +65,86 @@ impl From<u8> for DisposalMethod {
     }
 }
 
+/// Number of times an animation should be repeated, as announced by
+/// the NETSCAPE2.0 (or ANIMEXTS1.0) application extension
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LoopCount {
+    /// A loop count of 0 in the extension: repeat forever
+    Infinite,
+    /// Repeat the animation N more times after the first playback
+    Finite(u16),
+}
+
+impl From<u16> for LoopCount {
+    fn from(n: u16) -> Self {
+        match n {
+            0 => LoopCount::Infinite,
+            n => LoopCount::Finite(n),
+        }
+    }
+}
+
+/// Text stored in a Comment Extension (0xFE)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Comment {
+    /// Index of the first frame that follows the comment
+    /// (equal to the number of frames when it follows the last one)
+    pub frame_index: usize,
+    /// Raw bytes of every data sub-block, concatenated
+    pub data: Vec<u8>,
+}
+
+impl Comment {
+    pub fn bytes(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// Lossy text view of the comment
+    ///
+    /// The spec asks for 7-bit ASCII, in practice encoders write either
+    /// UTF-8 or Latin-1: valid UTF-8 is returned as is, anything else is
+    /// decoded as Latin-1.
+    pub fn text(&self) -> Cow<'_, str> {
+        match core::str::from_utf8(&self.data) {
+            Ok(text) => Cow::Borrowed(text),
+            Err(_) => {
+                Cow::Owned(self.data.iter().map(|&b| b as char).collect())
+            }
+        }
+    }
+}
+
+/// Plain Text Extension (0x01): text drawn on a grid of character
+/// cells, using colors from the Global Color Table
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PlainText {
+    pub left: u16,
+    pub top: u16,
+    pub width: u16,
+    pub height: u16,
+    pub cell_width: u8,
+    pub cell_height: u8,
+    pub fg_color_index: u8,
+    pub bg_color_index: u8,
+    pub text: Vec<u8>,
+}
+
+impl PlainText {
+    pub fn columns(&self) -> usize {
+        match self.cell_width {
+            0 => 0,
+            cw => self.width as usize / cw as usize,
+        }
+    }
+
+    pub fn rows(&self) -> usize {
+        match self.cell_height {
+            0 => 0,
+            ch => self.height as usize / ch as usize,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Copy, Default)]
 pub struct GraphicControl {
     pub disposal_method: DisposalMethod,
@@ -0,0 +1,373 @@
+//! GIFs built for the unit tests
+
+use alloc::{collections::BTreeMap, vec::Vec};
+
+use crate::structs::PlainText;
+
+/// Compresses `indices` as GIF image data, without the sub-blocks
+///
+/// A clear code is written before the index at `clear_at`, if any.
+/// Once the dictionary is full, the codes are written at 12 bits
+/// without clearing it.
+pub fn lzw(
+    min_code_size: u8,
+    indices: &[u8],
+    clear_at: Option<usize>,
+) -> Vec<u8> {
+    let clear = 1u16 << min_code_size;
+    let mut writer = CodeWriter {
+        out: Vec::new(),
+        bits: 0,
+        len: 0,
+        size: min_code_size + 1,
+        next: clear + 2,
+    };
+    let mut dictionary = BTreeMap::new();
+    let mut prefix: Option<u16> = None;
+
+    writer.write(clear);
+    for (i, &index) in indices.iter().enumerate() {
+        if Some(i) == clear_at {
+            if let Some(code) = prefix.take() {
+                writer.write(code);
+            }
+            writer.write(clear);
+            writer.size = min_code_size + 1;
+            writer.next = clear + 2;
+            dictionary.clear();
+        }
+
+        prefix = Some(match prefix {
+            None => index as u16,
+            Some(code) => match dictionary.get(&(code, index)) {
+                Some(&string) => string,
+                None => {
+                    writer.write(code);
+                    if writer.next < 4096 {
+                        dictionary.insert((code, index), writer.next);
+                        writer.next += 1;
+                    }
+                    index as u16
+                }
+            },
+        });
+    }
+    if let Some(code) = prefix {
+        writer.write(code);
+    }
+    writer.write(clear + 1);
+
+    writer.finish()
+}
+
+struct CodeWriter {
+    out: Vec<u8>,
+    bits: u32,
+    len: u8,
+    size: u8,
+    // Next code of the dictionary
+    next: u16,
+}
+
+impl CodeWriter {
+    fn write(&mut self, code: u16) {
+        self.bits |= (code as u32) << self.len;
+        self.len += self.size;
+        while self.len >= 8 {
+            self.out.push(self.bits as u8);
+            self.bits >>= 8;
+            self.len -= 8;
+        }
+
+        // The decoder adds the entry of this code when it reads the
+        // next one, and grows the codes as soon as it is full
+        if self.next == 1 << self.size && self.size < 12 {
+            self.size += 1;
+        }
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.len > 0 {
+            self.out.push(self.bits as u8);
+        }
+        self.out
+    }
+}
+
+/// Splits `data` in sub-blocks of `len` bytes, terminator included
+pub fn sub_blocks(data: &[u8], len: usize) -> Vec<u8> {
+    let mut out = Vec::new();
+    for block in data.chunks(len) {
+        out.push(block.len() as u8);
+        out.extend_from_slice(block);
+    }
+    out.push(0);
+    out
+}
+
+pub struct TestFrame {
+    pub left: u16,
+    pub top: u16,
+    pub width: u16,
+    pub height: u16,
+    /// In display order, reordered if `interlaced`
+    pub indices: Vec<u8>,
+    pub interlaced: bool,
+    /// RGB triplets
+    pub local_palette: Option<Vec<u8>>,
+    pub transparent: Option<u8>,
+    pub disposal: u8,
+    pub delay_cs: u16,
+    pub min_code_size: u8,
+}
+
+impl TestFrame {
+    pub fn new(left: u16, top: u16, width: u16, height: u16) -> Self {
+        let indices = (0..width as usize * height as usize)
+            .map(|i| (i * 7 / 3 % 8) as u8)
+            .collect();
+        Self {
+            left,
+            top,
+            width,
+            height,
+            indices,
+            interlaced: false,
+            local_palette: None,
+            transparent: None,
+            disposal: 0,
+            delay_cs: 10,
+            min_code_size: 3,
+        }
+    }
+
+    pub fn write(&self, out: &mut Vec<u8>) {
+        let flags =
+            self.disposal << 2 | self.transparent.is_some() as u8;
+        out.extend_from_slice(&[0x21, 0xF9, 4, flags]);
+        out.extend_from_slice(&self.delay_cs.to_le_bytes());
+        out.extend_from_slice(&[self.transparent.unwrap_or(0), 0]);
+
+        out.push(0x2C);
+        for value in [self.left, self.top, self.width, self.height] {
+            out.extend_from_slice(&value.to_le_bytes());
+        }
+        let mut packed = (self.interlaced as u8) << 6;
+        if let Some(palette) = &self.local_palette {
+            packed |= 0x80 | table_size_bits(palette.len() / 3);
+        }
+        out.push(packed);
+        if let Some(palette) = &self.local_palette {
+            out.extend_from_slice(palette);
+        }
+
+        let indices = match self.interlaced {
+            true => interlace(&self.indices, self.width as usize),
+            false => self.indices.clone(),
+        };
+        out.push(self.min_code_size);
+        let data = lzw(self.min_code_size, &indices, None);
+        out.extend_from_slice(&sub_blocks(&data, 255));
+    }
+}
+
+/// Builds a GIF89a with a Global Color Table of RGB triplets
+pub fn gif(
+    width: u16,
+    height: u16,
+    palette: &[u8],
+    frames: &[TestFrame],
+) -> Vec<u8> {
+    let mut out = b"GIF89a".to_vec();
+    out.extend_from_slice(&width.to_le_bytes());
+    out.extend_from_slice(&height.to_le_bytes());
+    out.extend_from_slice(&[
+        0x80 | table_size_bits(palette.len() / 3),
+        0,
+        0,
+    ]);
+    out.extend_from_slice(palette);
+
+    // Infinite loop
+    out.extend_from_slice(&[0x21, 0xFF, 11]);
+    out.extend_from_slice(b"NETSCAPE2.0");
+    out.extend_from_slice(&[3, 1, 0, 0, 0]);
+
+    for frame in frames {
+        frame.write(&mut out);
+    }
+    out.push(0x3B);
+    out
+}
+
+/// Appends a Comment Extension made of `sub_blocks`
+pub fn comment(out: &mut Vec<u8>, sub_blocks: &[&[u8]]) {
+    out.extend_from_slice(&[0x21, 0xFE]);
+    for block in sub_blocks {
+        out.push(block.len() as u8);
+        out.extend_from_slice(block);
+    }
+    out.push(0);
+}
+
+/// Appends a Plain Text Extension, its text in a single sub-block
+pub fn plain_text(out: &mut Vec<u8>, text: &PlainText) {
+    out.extend_from_slice(&[0x21, 0x01, 12]);
+    for value in [text.left, text.top, text.width, text.height] {
+        out.extend_from_slice(&value.to_le_bytes());
+    }
+    out.extend_from_slice(&[
+        text.cell_width,
+        text.cell_height,
+        text.fg_color_index,
+        text.bg_color_index,
+    ]);
+    out.extend_from_slice(&sub_blocks(&text.text, 255));
+}
+
+/// An animation using most features: transparency, disposal methods,
+/// interlacing, a local palette, a comment and long image data
+pub fn animation() -> Vec<u8> {
+    let palette: Vec<u8> = (0..8u8)
+        .flat_map(|i| [i * 30, 255 - i * 20, i * i])
+        .collect();
+
+    let mut noise = TestFrame::new(0, 0, 40, 30);
+    let mut state = 0x2545_F491_u32;
+    for index in &mut noise.indices {
+        state ^= state << 13;
+        state ^= state >> 17;
+        state ^= state << 5;
+        *index = (state % 8) as u8;
+    }
+
+    let mut small = TestFrame::new(3, 2, 6, 5);
+    small.interlaced = true;
+    small.transparent = Some(0);
+    small.disposal = 2;
+
+    let mut local = TestFrame::new(5, 4, 10, 7);
+    local.local_palette =
+        Some([255, 0, 0, 0, 255, 0, 0, 0, 255, 9, 9, 9].to_vec());
+    local.indices.iter_mut().for_each(|i| *i %= 4);
+    local.min_code_size = 2;
+    local.disposal = 3;
+
+    let mut last = TestFrame::new(1, 1, 38, 27);
+    last.interlaced = true;
+    last.transparent = Some(3);
+
+    let mut data = gif(40, 30, &palette, &[noise, small]);
+    data.pop();
+    comment(&mut data, &[b"hello"]);
+    for frame in [local, last] {
+        frame.write(&mut data);
+    }
+    data.push(0x3B);
+    data
+}
+
+/// Three images with a comment and a Plain Text Extension between
+/// the first two and a comment between the last two
+///
+/// Only the first image covers the canvas.
+#[cfg(feature = "std")]
+pub fn text_and_comments() -> Vec<u8> {
+    let text = PlainText {
+        left: 1,
+        top: 1,
+        width: 6,
+        height: 8,
+        cell_width: 6,
+        cell_height: 8,
+        fg_color_index: 1,
+        bg_color_index: 2,
+        text: b"x".to_vec(),
+    };
+
+    let palette: Vec<u8> = (0..24).map(|i| i * 10).collect();
+    let mut data = gif(16, 10, &palette, &[]);
+    data.pop();
+    TestFrame::new(0, 0, 16, 10).write(&mut data);
+    comment(&mut data, &[b"one"]);
+    plain_text(&mut data, &text);
+    TestFrame::new(2, 1, 4, 4).write(&mut data);
+    comment(&mut data, &[b"two"]);
+    TestFrame::new(3, 3, 5, 2).write(&mut data);
+    data.push(0x3B);
+    data
+}
+
+fn table_size_bits(entries: usize) -> u8 {
+    (entries.max(2).next_power_of_two().trailing_zeros() - 1) as u8
+}
+
+/// Reorders rows from display order to the order of the 4 passes
+fn interlace(indices: &[u8], width: usize) -> Vec<u8> {
+    let height = indices.len() / width;
+    let mut out = Vec::with_capacity(indices.len());
+    for (start, step) in [(0, 8), (4, 8), (2, 4), (1, 2)] {
+        for y in (start..height).step_by(step) {
+            out.extend_from_slice(
+                &indices[y * width..(y + 1) * width],
+            );
+        }
+    }
+    out
+}
+
+/// Counts the allocations of each thread, see `allocations`
+#[cfg(feature = "std")]
+struct CountingAllocator;
+
+#[cfg(feature = "std")]
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+#[cfg(feature = "std")]
+std::thread_local! {
+    static ALLOCATIONS: core::cell::Cell<usize> =
+        const { core::cell::Cell::new(0) };
+}
+
+#[cfg(feature = "std")]
+impl CountingAllocator {
+    fn count() {
+        // Allocations made while the thread exits are not counted
+        let _ = ALLOCATIONS.try_with(|n| n.set(n.get() + 1));
+    }
+}
+
+#[cfg(feature = "std")]
+unsafe impl std::alloc::GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: std::alloc::Layout) -> *mut u8 {
+        Self::count();
+        unsafe { std::alloc::System.alloc(layout) }
+    }
+
+    unsafe fn dealloc(
+        &self,
+        ptr: *mut u8,
+        layout: std::alloc::Layout,
+    ) {
+        unsafe { std::alloc::System.dealloc(ptr, layout) }
+    }
+
+    unsafe fn realloc(
+        &self,
+        ptr: *mut u8,
+        layout: std::alloc::Layout,
+        new_size: usize,
+    ) -> *mut u8 {
+        Self::count();
+        unsafe { std::alloc::System.realloc(ptr, layout, new_size) }
+    }
+}
+
+/// Returns how many allocations `f` makes on the current thread
+#[cfg(feature = "std")]
+pub fn allocations(f: impl FnOnce()) -> usize {
+    let start = ALLOCATIONS.get();
+    f();
+    ALLOCATIONS.get() - start
+}